@@ -0,0 +1,114 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Transform, Vector3};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+  pub min: Point3<f32>,
+  pub max: Point3<f32>,
+}
+
+impl Aabb {
+  /// An inverted box that any point or box will replace when merged.
+  pub fn empty() -> Self {
+    Self {
+      min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+      max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    }
+  }
+
+  pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Self {
+    points.into_iter().fold(Self::empty(), |aabb, point| aabb.grow(point))
+  }
+
+  pub fn center(&self) -> Point3<f32> {
+    self.min.midpoint(self.max)
+  }
+
+  pub fn extent(&self) -> Vector3<f32> {
+    self.max - self.min
+  }
+
+  pub fn grow(self, point: Point3<f32>) -> Self {
+    Self {
+      min: Point3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+      max: Point3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+  }
+
+  pub fn union(self, other: Aabb) -> Self {
+    if other.is_empty() {
+      return self;
+    }
+
+    self.grow(other.min).grow(other.max)
+  }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingSphere {
+  pub center: Point3<f32>,
+  pub radius: f32,
+}
+
+impl BoundingSphere {
+  /// Centers the sphere on the box of the points and grows it to reach the
+  /// farthest one. Not minimal, but tight enough for framing and culling.
+  pub fn from_points<I>(points: I) -> Self
+  where
+    I: IntoIterator<Item = Point3<f32>> + Clone,
+  {
+    let aabb = Aabb::from_points(points.clone());
+
+    if aabb.is_empty() {
+      return Self { center: Point3::origin(), radius: 0.0 };
+    }
+
+    let center = aabb.center();
+    let radius = points.into_iter()
+      .map(|point| point.distance2(center))
+      .fold(0.0, f32::max)
+      .sqrt();
+
+    Self { center, radius }
+  }
+
+  /// Sphere centered on the box around the given spheres, large enough to
+  /// contain all of them.
+  pub fn enclosing<I>(spheres: I) -> Self
+  where
+    I: IntoIterator<Item = BoundingSphere> + Clone,
+  {
+    let aabb = spheres.clone().into_iter().fold(Aabb::empty(), |aabb, sphere| {
+      let offset = Vector3::new(sphere.radius, sphere.radius, sphere.radius);
+
+      aabb.grow(sphere.center - offset).grow(sphere.center + offset)
+    });
+
+    if aabb.is_empty() {
+      return Self { center: Point3::origin(), radius: 0.0 };
+    }
+
+    let center = aabb.center();
+    let radius = spheres.into_iter()
+      .map(|sphere| (sphere.center - center).magnitude() + sphere.radius)
+      .fold(0.0, f32::max);
+
+    Self { center, radius }
+  }
+
+  /// Sphere containing this one after the matrix is applied. Scale is taken
+  /// from the longest basis vector so non-uniform scale is covered.
+  pub fn transform(&self, matrix: Matrix4<f32>) -> Self {
+    let scale = matrix.x.truncate().magnitude()
+      .max(matrix.y.truncate().magnitude())
+      .max(matrix.z.truncate().magnitude());
+
+    Self {
+      center: matrix.transform_point(self.center),
+      radius: self.radius * scale,
+    }
+  }
+}
@@ -0,0 +1,206 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{
+  bounds::Aabb,
+  mesh::MeshVertex,
+  ray::{Ray, TriangleHit},
+};
+
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// Interior nodes have `count == 0`, their left child directly follows them
+/// and `first` holds the index of the right child. Leaves cover
+/// `triangles[first..first + count]`.
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+  aabb: Aabb,
+  first: usize,
+  count: usize,
+}
+
+/// Bounding volume hierarchy over the triangles of an indexed mesh.
+#[derive(Debug, Default)]
+pub struct Bvh {
+  nodes: Vec<BvhNode>,
+  triangles: Vec<usize>,
+}
+
+impl Bvh {
+  pub fn new(vertices: &[MeshVertex], indices: &[u32]) -> Self {
+    let bounds = indices.chunks_exact(3).map(|triangle| {
+      if triangle.iter().all(|&i| (i as usize) < vertices.len()) {
+        Aabb::from_points(triangle.iter().map(|&i| Point3::from(vertices[i as usize].position)))
+      } else {
+        Aabb::empty()
+      }
+    }).collect::<Vec<_>>();
+    let mut triangles = (0..bounds.len())
+      .filter(|&triangle| !bounds[triangle].is_empty())
+      .collect::<Vec<_>>();
+    let mut nodes = Vec::new();
+
+    if !triangles.is_empty() {
+      let count = triangles.len();
+
+      build_node(&mut nodes, &mut triangles, &bounds, 0, count);
+    }
+
+    Self { nodes, triangles }
+  }
+
+  /// Closest triangle hit by the ray, if any.
+  pub fn intersect(&self, ray: &Ray, vertices: &[MeshVertex], indices: &[u32]) -> Option<TriangleHit> {
+    let mut closest: Option<TriangleHit> = None;
+    let mut stack = Vec::new();
+
+    if !self.nodes.is_empty() {
+      stack.push(0);
+    }
+
+    while let Some(node_index) = stack.pop() {
+      let node = &self.nodes[node_index];
+      let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+
+      if ray.intersect_aabb(&node.aabb, max_distance).is_none() {
+        continue;
+      }
+
+      if node.count == 0 {
+        let left = node_index + 1;
+        let right = node.first;
+        let left_distance = ray.intersect_aabb(&self.nodes[left].aabb, max_distance);
+        let right_distance = ray.intersect_aabb(&self.nodes[right].aabb, max_distance);
+
+        // Push the farther child first so the nearer one is visited first.
+        match (left_distance, right_distance) {
+          (Some(l), Some(r)) if l <= r => stack.extend([right, left]),
+          (Some(_), Some(_)) => stack.extend([left, right]),
+          (Some(_), None) => stack.push(left),
+          (None, Some(_)) => stack.push(right),
+          (None, None) => {}
+        }
+        continue;
+      }
+
+      for &triangle in &self.triangles[node.first..node.first + node.count] {
+        let corners = [
+          Point3::from(vertices[indices[triangle * 3] as usize].position),
+          Point3::from(vertices[indices[triangle * 3 + 1] as usize].position),
+          Point3::from(vertices[indices[triangle * 3 + 2] as usize].position),
+        ];
+
+        if let Some((distance, u, v)) = ray.intersect_triangle(corners[0], corners[1], corners[2]) {
+          if distance < closest.map_or(f32::INFINITY, |hit| hit.distance) {
+            let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize();
+
+            closest = Some(TriangleHit {
+              triangle,
+              barycentrics: Vector3::new(1.0 - u - v, u, v),
+              distance,
+              normal,
+            });
+          }
+        }
+      }
+    }
+
+    closest
+  }
+}
+
+fn build_node(
+  nodes: &mut Vec<BvhNode>,
+  triangles: &mut [usize],
+  bounds: &[Aabb],
+  first: usize,
+  count: usize,
+) -> usize {
+  let slice = &mut triangles[first..first + count];
+  let aabb = slice.iter().fold(Aabb::empty(), |aabb, &triangle| aabb.union(bounds[triangle]));
+  let node_index = nodes.len();
+
+  nodes.push(BvhNode { aabb, first, count });
+
+  if count <= MAX_LEAF_TRIANGLES {
+    return node_index;
+  }
+
+  let centroids = Aabb::from_points(slice.iter().map(|&triangle| bounds[triangle].center()));
+  let extent = centroids.extent();
+  let axis = if extent.x >= extent.y && extent.x >= extent.z {
+    0
+  } else if extent.y >= extent.z {
+    1
+  } else {
+    2
+  };
+  let centroid = |triangle: usize| bounds[triangle].center().to_vec()[axis];
+  let middle = count / 2;
+
+  slice.select_nth_unstable_by(middle, |&a, &b| {
+    centroid(a).partial_cmp(&centroid(b)).unwrap_or(std::cmp::Ordering::Equal)
+  });
+
+  build_node(nodes, triangles, bounds, first, middle);
+  let right = build_node(nodes, triangles, bounds, first + middle, count - middle);
+
+  nodes[node_index].first = right;
+  nodes[node_index].count = 0;
+
+  node_index
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    mesh::MeshBuilder,
+    noise::Noise,
+    terrain::HeightField,
+  };
+
+  /// Closest hit found by testing every triangle.
+  fn brute_force(ray: &Ray, vertices: &[MeshVertex], indices: &[u32]) -> Option<(usize, f32)> {
+    indices.chunks_exact(3).enumerate()
+      .filter_map(|(triangle, corners)| {
+        let corner = |i: usize| Point3::from(vertices[corners[i] as usize].position);
+
+        ray.intersect_triangle(corner(0), corner(1), corner(2)).map(|(distance, _, _)| (triangle, distance))
+      })
+      .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+  }
+
+  #[test]
+  fn finds_the_same_hits_as_every_triangle() {
+    let field = HeightField::from_noise(&Noise::new(Default::default()), 16, 0.5, 2.0);
+    let mut builder = MeshBuilder::new("Terrain");
+
+    field.add_to(&mut builder);
+
+    let mesh = &builder.build()[0];
+    let mut hits = 0;
+
+    for i in 0..20 {
+      for j in 0..20 {
+        let origin = Point3::new(i as f32 * 0.35 - 3.3, 4.0, j as f32 * 0.35 - 3.3);
+        let ray = Ray::new(origin, Vector3::new(0.2 - i as f32 * 0.02, -1.0, j as f32 * 0.02 - 0.2));
+        let bvh = mesh.bvh.intersect(&ray, &mesh.vertices, &mesh.indices);
+
+        match (bvh, brute_force(&ray, &mesh.vertices, &mesh.indices)) {
+          (Some(hit), Some((_, distance))) => {
+            assert!((hit.distance - distance).abs() < 1e-5, "{} != {}", hit.distance, distance);
+            hits += 1;
+          }
+          (None, None) => {}
+          (bvh, expected) => panic!("BVH found {:?}, every triangle {:?}", bvh, expected),
+        }
+      }
+    }
+
+    assert_eq!(hits, 400);
+
+    let miss = Ray::new(Point3::new(0.0, 4.0, 0.0), Vector3::unit_y());
+
+    assert!(mesh.bvh.intersect(&miss, &mesh.vertices, &mesh.indices).is_none());
+  }
+}
@@ -3,6 +3,7 @@ use cgmath::{
   InnerSpace,
   Matrix4,
   Point3,
+  Rad,
   Vector3,
 };
 use std::time::Duration;
@@ -11,7 +12,10 @@ use winit::{
   event::{ElementState, MouseScrollDelta, VirtualKeyCode},
 };
 
-use crate::camera::{Camera, CameraController};
+use crate::{
+  bounds::BoundingSphere,
+  camera::{Camera, CameraController},
+};
 
 #[derive(Debug)]
 pub struct OrbitCamera {
@@ -31,6 +35,22 @@ impl OrbitCamera {
       up,
     }
   }
+
+  /// Moves the target to the center of the sphere and backs the eye off
+  /// along its current direction until the sphere fits the vertical field
+  /// of view.
+  pub fn frame(&mut self, sphere: &BoundingSphere, fovy: Rad<f32>) {
+    let direction = self.eye - self.target;
+    let direction = if direction.magnitude2() > 0.0 {
+      direction.normalize()
+    } else {
+      Vector3::unit_z()
+    };
+    let distance = sphere.radius.max(0.1) / (fovy.0 / 2.0).sin();
+
+    self.target = sphere.center;
+    self.eye = self.target + direction * distance;
+  }
 }
 
 impl Camera for OrbitCamera {
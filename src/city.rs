@@ -0,0 +1,235 @@
+use cgmath::{Quaternion, Rad, Rotation3, Vector2, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+  house::{HouseSettings, RoofStyle},
+  instance::Instance,
+  mesh::MeshBuilder,
+};
+
+pub const CITY_STREET_MATERIAL: usize = 0;
+pub const CITY_SIDEWALK_MATERIAL: usize = 1;
+pub const CITY_MATERIALS: [(&str, [f32;4]); 2] = [("Street", STREET_COLOR), ("Sidewalk", SIDEWALK_COLOR)];
+
+/// Height of the blocks above the street.
+const CURB_HEIGHT: f32 = 0.05;
+/// Largest share of the width and depth of its lot a house may cover.
+const LOT_FILL: f32 = 0.8;
+/// Narrowest lot allowed, so splitting a block into lots always ends.
+const MIN_LOT_WIDTH: f32 = 0.25;
+const ROOF_STYLES: [RoofStyle; 5] = [
+  RoofStyle::Gable,
+  RoofStyle::Hip,
+  RoofStyle::Flat,
+  RoofStyle::Shed,
+  RoofStyle::Mansard,
+];
+const SIDEWALK_COLOR: [f32;4] = [0.7, 0.7, 0.68, 1.0];
+const STREET_COLOR: [f32;4] = [0.2, 0.2, 0.22, 1.0];
+
+#[derive(Copy, Clone, Debug)]
+pub struct CitySettings {
+  pub width: f32,
+  pub length: f32,
+  pub street_width: f32,
+  /// Blocks longer than this are split in two by another street.
+  pub block_size: f32,
+  /// Narrowest and widest frontage of a lot.
+  pub lot_min: f32,
+  pub lot_max: f32,
+  /// Distinct house models. Lots share them through instancing, so this
+  /// rather than the number of lots sets the cost of drawing the city.
+  pub variants: u32,
+  pub seed: u64,
+}
+
+impl Default for CitySettings {
+  fn default() -> Self {
+    Self {
+      width: 40.0,
+      length: 40.0,
+      street_width: 1.0,
+      block_size: 10.0,
+      lot_min: 2.0,
+      lot_max: 3.0,
+      variants: 8,
+      seed: 0,
+    }
+  }
+}
+
+/// Streets and blocks as one mesh, the houses to build, and where each
+/// house stands.
+pub struct City {
+  pub ground: MeshBuilder,
+  pub houses: Vec<HouseSettings>,
+  /// Instances of each house, in the same order as `houses`.
+  pub placements: Vec<Vec<Instance>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Rectangle {
+  min: Vector2<f32>,
+  max: Vector2<f32>,
+}
+
+impl Rectangle {
+  fn size(&self) -> Vector2<f32> {
+    self.max - self.min
+  }
+}
+
+/// City centered on the origin. Streets split the area in two across its
+/// longer side until every block is at most `block_size` long, then each
+/// block is divided into one or two rows of lots facing the streets.
+pub fn generate(settings: &CitySettings) -> City {
+  let settings = &CitySettings { lot_min: settings.lot_min.max(MIN_LOT_WIDTH), ..*settings };
+  let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+  let houses = (0..settings.variants.max(1)).map(|_| random_house(&mut rng)).collect::<Vec<_>>();
+  let half = Vector2::new(settings.width, settings.length) / 2.0;
+  let mut blocks = Vec::new();
+  let mut ground = MeshBuilder::new("City");
+  let mut placements = vec![Vec::new(); houses.len()];
+
+  split_blocks(Rectangle { min: -half, max: half }, settings, &mut rng, &mut blocks);
+
+  ground.set_material(CITY_STREET_MATERIAL);
+  ground.add_quad(
+    Vector3::new(-half.x, 0.0, -half.y),
+    Vector3::unit_x() * settings.width,
+    Vector3::unit_z() * settings.length,
+  );
+  ground.set_material(CITY_SIDEWALK_MATERIAL);
+
+  for block in &blocks {
+    let size = block.size();
+
+    ground.add_box(Vector3::new(block.min.x, 0.0, block.min.y), Vector3::new(size.x, CURB_HEIGHT, size.y));
+
+    for (lot, facing) in split_lots(block, settings, &mut rng) {
+      let variant = rng.gen_range(0..houses.len());
+      let house = &houses[variant];
+      // Houses face the street with their width along it.
+      let (frontage, depth) = if facing.x == 0.0 { (lot.size().x, lot.size().y) } else { (lot.size().y, lot.size().x) };
+      let scale = (LOT_FILL * frontage / (house.width + 2.0 * house.overhang))
+        .min(LOT_FILL * depth / (house.length + 2.0 * house.overhang))
+        .min(1.0);
+      let center = (lot.min + lot.max) / 2.0;
+
+      placements[variant].push(Instance {
+        position: Vector3::new(center.x, CURB_HEIGHT, center.y),
+        rotation: Quaternion::from_angle_y(Rad((-facing.x).atan2(-facing.y))),
+        scale: Vector3::new(scale, scale, scale),
+      });
+    }
+  }
+
+  City {
+    ground,
+    houses,
+    placements,
+  }
+}
+
+fn random_house(rng: &mut ChaCha8Rng) -> HouseSettings {
+  HouseSettings {
+    width: rng.gen_range(1.0..1.8),
+    length: rng.gen_range(1.0..1.8),
+    floors: rng.gen_range(1..=3),
+    roof: ROOF_STYLES[rng.gen_range(0..ROOF_STYLES.len())],
+    pitch: Rad(rng.gen_range(20.0_f32..40.0).to_radians()),
+    ..Default::default()
+  }
+}
+
+fn split_blocks(area: Rectangle, settings: &CitySettings, rng: &mut ChaCha8Rng, blocks: &mut Vec<Rectangle>) {
+  let size = area.size();
+  let axis = if size.x >= size.y { 0 } else { 1 };
+  let long = size[axis];
+
+  // Both halves must still hold a lot after the street takes its share.
+  if long <= settings.block_size || long < 2.0 * settings.lot_min + settings.street_width {
+    if size.x > 0.0 && size.y > 0.0 {
+      blocks.push(area);
+    }
+    return;
+  }
+
+  let cut = area.min[axis] + long * rng.gen_range(0.4..0.6);
+  let (mut first, mut second) = (area, area);
+
+  first.max[axis] = cut - settings.street_width / 2.0;
+  second.min[axis] = cut + settings.street_width / 2.0;
+  split_blocks(first, settings, rng, blocks);
+  split_blocks(second, settings, rng, blocks);
+}
+
+/// Lots along the longer side of a block, each with the direction of the
+/// street it faces. Blocks deep enough get a row of lots on both sides.
+fn split_lots(block: &Rectangle, settings: &CitySettings, rng: &mut ChaCha8Rng) -> Vec<(Rectangle, Vector2<f32>)> {
+  let size = block.size();
+  let (along, across) = if size.x >= size.y { (0, 1) } else { (1, 0) };
+  let rows = if size[across] >= 2.0 * settings.lot_min { 2 } else { 1 };
+  let depth = size[across] / rows as f32;
+  let mut lots = Vec::new();
+
+  for row in 0..rows {
+    // A single row faces a random side of the block.
+    let side = if rows == 2 { row } else { rng.gen_range(0..2) };
+    let mut facing = Vector2::new(0.0, 0.0);
+    let mut start = block.min[along];
+
+    facing[across] = if side == 0 { -1.0 } else { 1.0 };
+
+    while start < block.max[along] {
+      let mut end = start + if settings.lot_max > settings.lot_min {
+        rng.gen_range(settings.lot_min..settings.lot_max)
+      } else {
+        settings.lot_min
+      };
+
+      // Too little left for another lot joins this one.
+      if block.max[along] - end < settings.lot_min {
+        end = block.max[along];
+      }
+
+      let mut lot = *block;
+
+      lot.min[along] = start;
+      lot.max[along] = end;
+      lot.min[across] = block.min[across] + row as f32 * depth;
+      lot.max[across] = lot.min[across] + depth;
+      lots.push((lot, facing));
+      start = end;
+    }
+  }
+
+  lots
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{instance_bytes, mesh_bytes};
+
+  #[test]
+  fn lots_without_width_still_end() {
+    let city = generate(&CitySettings { width: 8.0, length: 8.0, lot_min: 0.0, lot_max: 0.0, ..Default::default() });
+
+    assert!(city.placements.iter().map(Vec::len).sum::<usize>() > 0);
+  }
+
+  #[test]
+  fn city_is_the_same_for_a_seed() {
+    let settings = CitySettings { seed: 7, ..Default::default() };
+    let (first, second) = (generate(&settings), generate(&settings));
+
+    assert_eq!(mesh_bytes(&first.ground), mesh_bytes(&second.ground));
+    assert_eq!(format!("{:?}", first.houses), format!("{:?}", second.houses));
+
+    for (first, second) in first.placements.iter().zip(&second.placements) {
+      assert_eq!(instance_bytes(first), instance_bytes(second));
+    }
+  }
+}
@@ -1,3 +1,22 @@
+use cgmath::{InnerSpace, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::mesh::COLOR;
+
+const HEIGHT_HIGH_COLOR: [f32;4] = [0.55, 0.5, 0.4, 1.0];
+const HEIGHT_LOW_COLOR: [f32;4] = [0.2, 0.45, 0.15, 1.0];
+const NORMAL_DOWN_COLOR: [f32;4] = [0.25, 0.2, 0.2, 1.0];
+const NORMAL_SIDE_COLOR: [f32;4] = [0.6, 0.55, 0.5, 1.0];
+const NORMAL_UP_COLOR: [f32;4] = [0.3, 0.6, 0.25, 1.0];
+const PALETTE: [[f32;4]; 5] = [
+  [0.85, 0.35, 0.3, 1.0],
+  [0.95, 0.75, 0.3, 1.0],
+  [0.4, 0.7, 0.35, 1.0],
+  [0.3, 0.55, 0.8, 1.0],
+  [0.6, 0.4, 0.75, 1.0],
+];
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ColorUniform {
@@ -14,3 +33,141 @@ impl From<ColorUniform> for wgpu::Color {
     }
   }
 }
+
+/// Generators that can recolor whole models from the command line.
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum ColorScheme {
+  /// Low parts green and high parts gray.
+  Height,
+  /// Tops, sides and undersides in different colors.
+  Normal,
+  /// Cycles through a palette face by face.
+  Face,
+  /// Random palette color for each face.
+  Random,
+}
+
+impl ColorScheme {
+  /// Generator for a model reaching from `min` to `max` in height. Random
+  /// colors are drawn from `seed`.
+  pub fn generator(self, min: f32, max: f32, seed: u64) -> ColorGenerator {
+    match self {
+      ColorScheme::Height => ColorGenerator::Height {
+        low: HEIGHT_LOW_COLOR,
+        high: HEIGHT_HIGH_COLOR,
+        min,
+        max,
+      },
+      ColorScheme::Normal => ColorGenerator::Normal {
+        up: NORMAL_UP_COLOR,
+        side: NORMAL_SIDE_COLOR,
+        down: NORMAL_DOWN_COLOR,
+      },
+      ColorScheme::Face => ColorGenerator::FaceIndex(PALETTE.to_vec()),
+      ColorScheme::Random => ColorGenerator::RandomPalette {
+        palette: PALETTE.to_vec(),
+        seed,
+      },
+    }
+  }
+}
+
+/// Strategies for coloring the vertices of a mesh after its geometry has
+/// been built.
+pub enum ColorGenerator {
+  /// Blends from `low` to `high` as the vertex height goes from `min` to `max`.
+  Height {
+    low: [f32; 4],
+    high: [f32; 4],
+    min: f32,
+    max: f32,
+  },
+  /// Blends from `side` towards `up` or `down` by how vertical the normal is.
+  Normal {
+    up: [f32; 4],
+    side: [f32; 4],
+    down: [f32; 4],
+  },
+  /// Cycles through the palette, one color per face.
+  FaceIndex(Vec<[f32; 4]>),
+  /// Picks a color from the palette for each face.
+  RandomPalette {
+    palette: Vec<[f32; 4]>,
+    seed: u64,
+  },
+}
+
+impl ColorGenerator {
+  /// Colors handed out one face at a time, or `None` for generators that
+  /// color each vertex on its own.
+  pub fn face_colors(&self) -> Option<FaceColors<'_>> {
+    match self {
+      ColorGenerator::FaceIndex(palette) => Some(FaceColors {
+        palette,
+        index: 0,
+        rng: None,
+      }),
+      ColorGenerator::RandomPalette { palette, seed } => Some(FaceColors {
+        palette,
+        index: 0,
+        rng: Some(ChaCha8Rng::seed_from_u64(*seed)),
+      }),
+      _ => None,
+    }
+  }
+
+  /// Color of a single vertex, or `None` for generators that color whole
+  /// faces through `face_colors`.
+  pub fn vertex_color(&self, position: Vector3<f32>, normal: Vector3<f32>) -> Option<[f32; 4]> {
+    match self {
+      ColorGenerator::Height { low, high, min, max } => {
+        let t = if max > min { (position.y - min) / (max - min) } else { 0.0 };
+
+        Some(lerp(*low, *high, t))
+      }
+      ColorGenerator::Normal { up, side, down } => {
+        let y = if normal.magnitude2() > 0.0 { normal.normalize().y } else { 0.0 };
+
+        if y >= 0.0 {
+          Some(lerp(*side, *up, y))
+        } else {
+          Some(lerp(*side, *down, -y))
+        }
+      }
+      ColorGenerator::FaceIndex(_) | ColorGenerator::RandomPalette { .. } => None,
+    }
+  }
+}
+
+pub struct FaceColors<'a> {
+  palette: &'a [[f32; 4]],
+  index: usize,
+  rng: Option<ChaCha8Rng>,
+}
+
+impl<'a> FaceColors<'a> {
+  pub fn next_color(&mut self) -> [f32; 4] {
+    if self.palette.is_empty() {
+      return COLOR;
+    }
+
+    let index = match self.rng.as_mut() {
+      Some(rng) => rng.gen_range(0..self.palette.len()),
+      None => self.index % self.palette.len(),
+    };
+
+    self.index += 1;
+    self.palette[index]
+  }
+}
+
+fn lerp(from: [f32; 4], to: [f32; 4], t: f32) -> [f32; 4] {
+  let t = t.clamp(0.0, 1.0);
+  let mut color = [0.0; 4];
+
+  for i in 0..4 {
+    color[i] = from[i] + (to[i] - from[i]) * t;
+  }
+
+  color
+}
@@ -1,6 +1,7 @@
 use std::ops::Range;
 
 use crate::{
+  material::Material,
   mesh::Mesh,
   model::Model,
 };
@@ -9,6 +10,7 @@ pub trait DrawModel<'a> {
   fn draw_mesh(
     &mut self,
     mesh: &'a Mesh,
+    material: &'a Material,
     ambient: &'a wgpu::BindGroup,
     camera: &'a wgpu::BindGroup,
     light: &'a wgpu::BindGroup,
@@ -16,6 +18,7 @@ pub trait DrawModel<'a> {
   fn draw_mesh_instanced(
     &mut self,
     mesh: &'a Mesh,
+    material: &'a Material,
     instances: Range<u32>,
     ambient: &'a wgpu::BindGroup,
     camera: &'a wgpu::BindGroup,
@@ -36,6 +39,15 @@ pub trait DrawModel<'a> {
     camera: &'a wgpu::BindGroup,
     light: &'a wgpu::BindGroup,
   );
+  fn draw_model_instanced_with_material(
+    &mut self,
+    model: &'a Model,
+    material: &'a Material,
+    instances: Range<u32>,
+    ambient: &'a wgpu::BindGroup,
+    camera: &'a wgpu::BindGroup,
+    light: &'a wgpu::BindGroup,
+  );
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
@@ -45,16 +57,18 @@ where
   fn draw_mesh(
     &mut self,
     mesh: &'b Mesh,
+    material: &'b Material,
     ambient: &'b wgpu::BindGroup,
     camera: &'b wgpu::BindGroup,
     light: &'a wgpu::BindGroup,
   ) {
-    self.draw_mesh_instanced(mesh, 0..1, ambient, camera, light);
+    self.draw_mesh_instanced(mesh, material, 0..1, ambient, camera, light);
   }
 
   fn draw_mesh_instanced(
     &mut self,
     mesh: &'b Mesh,
+    material: &'b Material,
     instances: Range<u32>,
     ambient: &'b wgpu::BindGroup,
     camera: &'b wgpu::BindGroup,
@@ -65,6 +79,7 @@ where
     self.set_bind_group(0, ambient, &[]);
     self.set_bind_group(1, camera, &[]);
     self.set_bind_group(2, light, &[]);
+    self.set_bind_group(3, &material.bind_group, &[]);
     self.draw_indexed(0..mesh.num_elements, 0, instances);
   }
 
@@ -85,9 +100,27 @@ where
     ambient: &'b wgpu::BindGroup,
     camera: &'b wgpu::BindGroup,
     light: &'a wgpu::BindGroup,
+  ) {
+    // Models give every slot a material, see `Model::from_builder`, so a
+    // mesh is only skipped if its model was put together by hand.
+    for mesh in &model.meshes {
+      if let Some(material) = model.materials.get(mesh.material) {
+        self.draw_mesh_instanced(mesh, material, instances.clone(), ambient, camera, light);
+      }
+    }
+  }
+
+  fn draw_model_instanced_with_material(
+    &mut self,
+    model: &'b Model,
+    material: &'b Material,
+    instances: Range<u32>,
+    ambient: &'b wgpu::BindGroup,
+    camera: &'b wgpu::BindGroup,
+    light: &'a wgpu::BindGroup,
   ) {
     for mesh in &model.meshes {
-      self.draw_mesh_instanced(mesh, instances.clone(), ambient, camera, light);
+      self.draw_mesh_instanced(mesh, material, instances.clone(), ambient, camera, light);
     }
   }
 }
@@ -0,0 +1,300 @@
+use cgmath::{InnerSpace, Rad, Vector2};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::terrain::HeightField;
+
+/// Droplets started before the tiles are shifted to a new offset.
+const DROPLETS_PER_BATCH: u32 = 4096;
+const GRAVITY: f32 = 4.0;
+const INERTIA: f32 = 0.05;
+const MAX_DROPLET_STEPS: u32 = 64;
+/// Carrying capacity kept on flat ground so droplets still deposit slowly.
+const MIN_CAPACITY: f32 = 0.01;
+/// Width in points of the square tiles that droplets are simulated in.
+/// Droplets stay inside the tile they start in, so tiles run in parallel
+/// without sharing any points.
+const TILE_SIZE: u32 = 64;
+
+/// Particle-based hydraulic erosion: raindrops run downhill, pick up
+/// sediment while they speed up and drop it where they slow down or
+/// evaporate.
+#[derive(Copy, Clone, Debug)]
+pub struct HydraulicSettings {
+  /// Number of droplets.
+  pub iterations: u32,
+  /// Water each droplet starts with.
+  pub rain: f32,
+  /// Fraction of its water a droplet loses on every step.
+  pub evaporation: f32,
+  /// Sediment carried per unit of speed, water and drop in height.
+  pub sediment_capacity: f32,
+  /// Fraction of the spare capacity picked up on every step.
+  pub erosion_rate: f32,
+  /// Fraction of the excess sediment dropped on every step.
+  pub deposition_rate: f32,
+  pub seed: u64,
+}
+
+impl Default for HydraulicSettings {
+  fn default() -> Self {
+    Self {
+      iterations: 50_000,
+      rain: 1.0,
+      evaporation: 0.02,
+      sediment_capacity: 4.0,
+      erosion_rate: 0.3,
+      deposition_rate: 0.3,
+      seed: 0,
+    }
+  }
+}
+
+/// Thermal erosion: material slides off any slope steeper than the angle of
+/// repose until it settles.
+#[derive(Copy, Clone, Debug)]
+pub struct ThermalSettings {
+  pub iterations: u32,
+  /// Angle of repose, the steepest slope that does not slide.
+  pub talus: Rad<f32>,
+  /// Fraction of the excess material moved on each iteration.
+  pub rate: f32,
+}
+
+impl Default for ThermalSettings {
+  fn default() -> Self {
+    Self {
+      iterations: 50,
+      talus: Rad(35.0_f32.to_radians()),
+      rate: 0.5,
+    }
+  }
+}
+
+/// Erosion passes run on a terrain after it is generated. Hydraulic runs
+/// first and thermal smooths the steep banks it leaves behind.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ErosionSettings {
+  pub hydraulic: Option<HydraulicSettings>,
+  pub thermal: Option<ThermalSettings>,
+}
+
+impl ErosionSettings {
+  pub fn apply(&self, field: &mut HeightField) {
+    if let Some(settings) = &self.hydraulic {
+      hydraulic(field, settings);
+    }
+    if let Some(settings) = &self.thermal {
+      thermal(field, settings);
+    }
+  }
+}
+
+/// Droplet start points are drawn up front from the seed and every tile
+/// runs its own droplets in order, so the result does not depend on the
+/// number of threads. The tile grid moves between batches so tile edges do
+/// not leave seams.
+pub fn hydraulic(field: &mut HeightField, settings: &HydraulicSettings) {
+  if field.columns < 2 || field.rows < 2 {
+    return;
+  }
+
+  let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+  let mut remaining = settings.iterations;
+
+  while remaining > 0 {
+    let count = remaining.min(DROPLETS_PER_BATCH);
+    let offset = (rng.gen_range(0..TILE_SIZE), rng.gen_range(0..TILE_SIZE));
+    let mut tiles = BTreeMap::<(u32, u32), Vec<Vector2<f32>>>::new();
+
+    for _ in 0..count {
+      let start = Vector2::new(
+        rng.gen_range(0.0..(field.columns - 1) as f32),
+        rng.gen_range(0.0..(field.rows - 1) as f32),
+      );
+      let tile = ((start.x as u32 + offset.0) / TILE_SIZE, (start.y as u32 + offset.1) / TILE_SIZE);
+
+      tiles.entry(tile).or_default().push(start);
+    }
+
+    let heights = &field.heights;
+    let columns = field.columns;
+    let rows = field.rows;
+    let results = tiles.into_par_iter().map(|((tile_x, tile_y), starts)| {
+      let x0 = (tile_x * TILE_SIZE).saturating_sub(offset.0);
+      let y0 = (tile_y * TILE_SIZE).saturating_sub(offset.1);
+      let width = ((tile_x + 1) * TILE_SIZE - offset.0).min(columns) - x0;
+      let height = ((tile_y + 1) * TILE_SIZE - offset.1).min(rows) - y0;
+      let mut tile = (y0..y0 + height)
+        .flat_map(|y| {
+          let row = (y * columns + x0) as usize;
+
+          heights[row..row + width as usize].iter().cloned()
+        })
+        .collect::<Vec<_>>();
+
+      for start in starts {
+        let local = start - Vector2::new(x0 as f32, y0 as f32);
+
+        run_droplet(&mut tile, width, height, local, settings);
+      }
+
+      (x0, y0, width, tile)
+    }).collect::<Vec<_>>();
+
+    for (x0, y0, width, tile) in results {
+      for (y, row) in tile.chunks_exact(width as usize).enumerate() {
+        let start = ((y0 + y as u32) * columns + x0) as usize;
+
+        field.heights[start..start + width as usize].copy_from_slice(row);
+      }
+    }
+
+    remaining -= count;
+  }
+}
+
+pub fn thermal(field: &mut HeightField, settings: &ThermalSettings) {
+  let columns = field.columns as usize;
+  let rows = field.rows as usize;
+  let talus = field.spacing * settings.talus.0.tan();
+  let neighbours = |index: usize| {
+    let (column, row) = (index % columns, index / columns);
+
+    [
+      (column > 0).then(|| index - 1),
+      (column + 1 < columns).then(|| index + 1),
+      (row > 0).then(|| index - columns),
+      (row + 1 < rows).then(|| index + columns),
+    ]
+  };
+
+  for _ in 0..settings.iterations {
+    let heights = &field.heights;
+    // Material each point sends to each of its neighbours, in the order
+    // returned by `neighbours`.
+    let outflow = (0..heights.len()).into_par_iter().map(|index| {
+      let mut excess = [0.0; 4];
+
+      for (slot, neighbour) in neighbours(index).iter().enumerate() {
+        if let Some(neighbour) = neighbour {
+          excess[slot] = (heights[index] - heights[*neighbour] - talus).max(0.0);
+        }
+      }
+
+      let total = excess.iter().sum::<f32>();
+      let steepest = excess.iter().cloned().fold(0.0, f32::max);
+
+      if total > 0.0 {
+        excess.map(|amount| settings.rate * steepest / 2.0 * amount / total)
+      } else {
+        excess
+      }
+    }).collect::<Vec<_>>();
+
+    field.heights.par_iter_mut().enumerate().for_each(|(index, height)| {
+      *height -= outflow[index].iter().sum::<f32>();
+
+      for (slot, neighbour) in neighbours(index).iter().enumerate() {
+        if let Some(neighbour) = neighbour {
+          // Left and right swap slots, as do back and front.
+          *height += outflow[*neighbour][slot ^ 1];
+        }
+      }
+    });
+  }
+}
+
+fn run_droplet(heights: &mut [f32], columns: u32, rows: u32, start: Vector2<f32>, settings: &HydraulicSettings) {
+  let mut position = start;
+  let mut direction = Vector2::new(0.0, 0.0);
+  let mut speed = 1.0_f32;
+  let mut water = settings.rain;
+  let mut sediment = 0.0_f32;
+  let inside = |position: Vector2<f32>| {
+    position.x >= 0.0
+      && position.y >= 0.0
+      && position.x < (columns - 1) as f32
+      && position.y < (rows - 1) as f32
+  };
+
+  if !inside(position) {
+    return;
+  }
+
+  for _ in 0..MAX_DROPLET_STEPS {
+    let (height, gradient) = height_and_gradient(heights, columns, position);
+
+    direction = direction * INERTIA - gradient * (1.0 - INERTIA);
+
+    if direction.magnitude2() == 0.0 {
+      break;
+    }
+
+    direction = direction.normalize();
+
+    let previous = position;
+
+    position += direction;
+
+    if !inside(position) {
+      break;
+    }
+
+    let drop = height - height_and_gradient(heights, columns, position).0;
+    let capacity = (drop * speed * water * settings.sediment_capacity).max(MIN_CAPACITY);
+
+    if drop < 0.0 || sediment > capacity {
+      // Fill the pit the droplet climbed out of, or shed what it cannot carry.
+      let amount = if drop < 0.0 {
+        sediment.min(-drop)
+      } else {
+        (sediment - capacity) * settings.deposition_rate
+      };
+
+      sediment -= amount;
+      spread(heights, columns, previous, amount);
+    } else {
+      let amount = ((capacity - sediment) * settings.erosion_rate).min(drop);
+
+      sediment += amount;
+      spread(heights, columns, previous, -amount);
+    }
+
+    speed = (speed * speed + drop * GRAVITY).max(0.0).sqrt();
+    water *= 1.0 - settings.evaporation;
+  }
+}
+
+/// Bilinear height at a point in grid units, and its slope along each axis.
+fn height_and_gradient(heights: &[f32], columns: u32, position: Vector2<f32>) -> (f32, Vector2<f32>) {
+  let (x, y) = (position.x.floor(), position.y.floor());
+  let (u, v) = (position.x - x, position.y - y);
+  let index = y as usize * columns as usize + x as usize;
+  let h00 = heights[index];
+  let h10 = heights[index + 1];
+  let h01 = heights[index + columns as usize];
+  let h11 = heights[index + columns as usize + 1];
+  let height = h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+  let gradient = Vector2::new(
+    (h10 - h00) * (1.0 - v) + (h11 - h01) * v,
+    (h01 - h00) * (1.0 - u) + (h11 - h10) * u,
+  );
+
+  (height, gradient)
+}
+
+/// Adds `amount` to the four points around a position, weighted by how
+/// close the position is to each.
+fn spread(heights: &mut [f32], columns: u32, position: Vector2<f32>, amount: f32) {
+  let (x, y) = (position.x.floor(), position.y.floor());
+  let (u, v) = (position.x - x, position.y - y);
+  let index = y as usize * columns as usize + x as usize;
+
+  heights[index] += amount * (1.0 - u) * (1.0 - v);
+  heights[index + 1] += amount * u * (1.0 - v);
+  heights[index + columns as usize] += amount * (1.0 - u) * v;
+  heights[index + columns as usize + 1] += amount * u * v;
+}
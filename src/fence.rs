@@ -0,0 +1,235 @@
+use anyhow::{bail, Result};
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector2, Vector3};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use crate::{
+  mesh::MeshBuilder,
+  model::Model,
+  modifier::{catmull_rom, sample_evenly, CURVE_SAMPLES},
+  terrain::HeightField,
+};
+
+pub const FENCE_POST_MATERIAL: usize = 0;
+pub const FENCE_RAIL_MATERIAL: usize = 1;
+pub const FENCE_PICKET_MATERIAL: usize = 2;
+pub const FENCE_WIRE_MATERIAL: usize = 3;
+pub const FENCE_MATERIALS: [(&str, [f32;4]); 4] = [
+  ("Post", POST_COLOR),
+  ("Rail", RAIL_COLOR),
+  ("Picket", PICKET_COLOR),
+  ("Wire", WIRE_COLOR),
+];
+
+const PICKET_COLOR: [f32;4] = [0.9, 0.88, 0.82, 1.0];
+/// Height of pickets as a fraction of the fence height.
+const PICKET_HEIGHT: f32 = 0.9;
+const PICKET_THICKNESS: f32 = 0.02;
+const POST_COLOR: [f32;4] = [0.35, 0.25, 0.15, 1.0];
+/// How far posts reach into the ground, so they do not float where the
+/// ground between samples dips.
+const POST_SINK: f32 = 0.1;
+/// Heights of the lowest and highest rail as fractions of the fence height.
+const RAIL_HIGH: f32 = 0.85;
+const RAIL_LOW: f32 = 0.25;
+const RAIL_COLOR: [f32;4] = [0.45, 0.32, 0.2, 1.0];
+const WIRE_COLOR: [f32;4] = [0.6, 0.6, 0.62, 1.0];
+const WIRE_THICKNESS: f32 = 0.01;
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum FenceStyle {
+  /// Wooden rails between the posts.
+  Rails,
+  /// Upright pickets nailed to two rails.
+  Pickets,
+  /// Thin strands of wire between the posts.
+  Wire,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FenceSettings {
+  pub style: FenceStyle,
+  /// Largest distance between neighbouring posts.
+  pub spacing: f32,
+  pub height: f32,
+  pub post_width: f32,
+  /// Rails, or strands of wire, between each pair of posts.
+  pub rails: u32,
+  pub rail_size: f32,
+  pub picket_width: f32,
+  pub picket_spacing: f32,
+  /// Follow a Catmull-Rom curve through the points instead of straight
+  /// lines, with posts spaced evenly along it.
+  pub smooth: bool,
+}
+
+impl Default for FenceSettings {
+  fn default() -> Self {
+    Self {
+      style: FenceStyle::Rails,
+      spacing: 2.0,
+      height: 1.0,
+      post_width: 0.1,
+      rails: 2,
+      rail_size: 0.06,
+      picket_width: 0.08,
+      picket_spacing: 0.15,
+      smooth: false,
+    }
+  }
+}
+
+/// Points a fence runs through, as read from a JSON file such as
+/// `{ "points": [[0, 0], [4, 0], [4, 3]] }`. Points are `[x, z]` on the
+/// ground or `[x, y, z]`.
+#[derive(Deserialize)]
+struct FenceFile {
+  points: Vec<Vec<f32>>,
+}
+
+pub fn load_points<P: AsRef<Path>>(path: P) -> Result<Vec<Vector3<f32>>> {
+  let file: FenceFile = serde_json::from_str(&fs::read_to_string(path.as_ref())?)?;
+  let points = file.points.iter().map(|point| match point[..] {
+    [x, z] => Ok(Vector3::new(x, 0.0, z)),
+    [x, y, z] => Ok(Vector3::new(x, y, z)),
+    _ => bail!("fence points need two or three coordinates, not {}", point.len()),
+  }).collect::<Result<Vec<_>>>()?;
+
+  if points.len() < 2 {
+    bail!("{} needs at least two points", path.as_ref().display());
+  }
+
+  Ok(points)
+}
+
+/// Posts along the points with rails, pickets or wire between them. With a
+/// surface and its world matrix, every post and picket stands on the ground
+/// below it.
+pub fn build(
+  points: &[Vector3<f32>],
+  settings: &FenceSettings,
+  surface: Option<(&HeightField, Matrix4<f32>)>,
+) -> MeshBuilder {
+  let mut builder = MeshBuilder::new("Fence");
+  let surface = surface.and_then(|(field, matrix)| matrix.invert().map(|inverse| (field, matrix, inverse)));
+  let ground = |point: Vector3<f32>| {
+    // The field is sampled in its own space and the height brought back
+    // into world space.
+    let height = surface.and_then(|(field, matrix, inverse)| {
+      let local = inverse.transform_point(Point3::new(point.x, point.y, point.z));
+      let height = field.sample(local.x, local.z)?;
+
+      Some(matrix.transform_point(Point3::new(local.x, height, local.z)).y)
+    }).unwrap_or(point.y);
+
+    Vector3::new(point.x, height, point.z)
+  };
+  let posts = post_positions(points, settings).into_iter().map(ground).collect::<Vec<_>>();
+
+  builder.set_material(FENCE_POST_MATERIAL);
+
+  for &post in &posts {
+    Model::add_post(
+      &mut builder,
+      post - Vector3::unit_y() * POST_SINK,
+      settings.post_width,
+      settings.post_width,
+      settings.height + POST_SINK,
+    );
+  }
+
+  for pair in posts.windows(2) {
+    let (start, end) = (pair[0], pair[1]);
+    let direction = end - start;
+    let across = Vector3::new(-direction.z, 0.0, direction.x);
+
+    if across.magnitude2() == 0.0 {
+      continue;
+    }
+
+    let across = across.normalize();
+    let rail = |builder: &mut MeshBuilder, fraction: f32, size: Vector2<f32>| {
+      let offset = Vector3::unit_y() * settings.height * fraction;
+
+      builder.add_beam(start + offset, end + offset, across, size);
+    };
+
+    match settings.style {
+      FenceStyle::Rails => {
+        builder.set_material(FENCE_RAIL_MATERIAL);
+
+        for fraction in rail_fractions(settings.rails) {
+          rail(&mut builder, fraction, Vector2::new(settings.rail_size / 2.0, settings.rail_size));
+        }
+      }
+      FenceStyle::Pickets => {
+        builder.set_material(FENCE_RAIL_MATERIAL);
+
+        for fraction in rail_fractions(2) {
+          rail(&mut builder, fraction, Vector2::new(settings.rail_size / 2.0, settings.rail_size));
+        }
+
+        builder.set_material(FENCE_PICKET_MATERIAL);
+
+        // Pickets sit on the outside of the rails.
+        let outside = across * (settings.rail_size + PICKET_THICKNESS) / 2.0;
+        let count = (direction.magnitude() / settings.picket_spacing.max(PICKET_THICKNESS)).floor() as u32;
+        let along = Vector3::new(direction.x, 0.0, direction.z).normalize();
+
+        for i in 1..count {
+          let t = i as f32 / count as f32;
+          let base = ground(start + direction * t) - Vector3::unit_y() * POST_SINK;
+          let top = (start + direction * t).y + settings.height * PICKET_HEIGHT;
+
+          builder.add_beam(
+            base + outside,
+            Vector3::new(base.x, top, base.z) + outside,
+            along,
+            Vector2::new(settings.picket_width, PICKET_THICKNESS),
+          );
+        }
+      }
+      FenceStyle::Wire => {
+        builder.set_material(FENCE_WIRE_MATERIAL);
+
+        for fraction in rail_fractions(settings.rails) {
+          rail(&mut builder, fraction, Vector2::new(WIRE_THICKNESS, WIRE_THICKNESS));
+        }
+      }
+    }
+  }
+
+  builder
+}
+
+/// Posts at both ends and at every corner of the points, or along the
+/// curve through them when smoothed, no more than `spacing` apart.
+fn post_positions(points: &[Vector3<f32>], settings: &FenceSettings) -> Vec<Vector3<f32>> {
+  let spans = if settings.smooth {
+    vec![catmull_rom(points, CURVE_SAMPLES)]
+  } else {
+    points.windows(2).map(|pair| pair.to_vec()).collect()
+  };
+  let mut posts = Vec::new();
+
+  for span in spans {
+    let length = span.windows(2).map(|pair| (pair[1] - pair[0]).magnitude()).sum::<f32>();
+    let count = (length / settings.spacing.max(settings.post_width)).ceil().max(1.0) as u32;
+    // Corners are shared with the span before.
+    let skip = if posts.is_empty() { 0 } else { 1 };
+
+    posts.extend(sample_evenly(&span, count + 1).into_iter().skip(skip).map(|(position, _)| position));
+  }
+
+  posts
+}
+
+/// Heights of `count` rails as fractions of the fence height, spread from
+/// the lowest to the highest rail.
+fn rail_fractions(count: u32) -> Vec<f32> {
+  match count {
+    0 => Vec::new(),
+    1 => vec![RAIL_HIGH],
+    _ => (0..count).map(|i| RAIL_LOW + (RAIL_HIGH - RAIL_LOW) * i as f32 / (count - 1) as f32).collect(),
+  }
+}
@@ -0,0 +1,279 @@
+use cgmath::{
+  InnerSpace,
+  Point3,
+  Quaternion,
+  Rad,
+  Rotation3,
+  Vector2,
+  Vector3,
+};
+use std::f32::consts::PI;
+
+use crate::{
+  mesh::MeshBuilder,
+  model::Model,
+  ray::{self, Ray},
+  transform::Transform,
+};
+
+const AXIS_MATERIALS: [(&str, [f32; 4]); 3] = [
+  ("X", [0.9, 0.15, 0.15, 1.0]),
+  ("Y", [0.15, 0.8, 0.15, 1.0]),
+  ("Z", [0.15, 0.3, 0.95, 1.0]),
+];
+const MIN_SCALE: f32 = 0.01;
+const PARALLEL_EPSILON: f32 = 1e-4;
+const RING_SEGMENTS: u32 = 48;
+const SHAFT_START: f32 = 0.15;
+const SHAFT_THICKNESS: f32 = 0.03;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GizmoMode {
+  Translate,
+  Rotate,
+  Scale,
+}
+
+struct Drag {
+  axis: usize,
+  center: Point3<f32>,
+  /// World direction the drag is measured along. Scaling follows the
+  /// target's own axis, so for it this is the axis turned by the target's
+  /// rotation.
+  direction: Vector3<f32>,
+  pivot: Point3<f32>,
+  start: Transform,
+  start_parameter: f32,
+  start_vector: Vector3<f32>,
+}
+
+/// Axis handles drawn over the selected model. Each handle is its own
+/// material slot so the mesh that a ray hits identifies the axis.
+pub struct Gizmo {
+  mode: GizmoMode,
+  center: Point3<f32>,
+  drag: Option<Drag>,
+  rotate: Model,
+  scale: Model,
+  translate: Model,
+}
+
+impl Gizmo {
+  pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+    Self {
+      mode: GizmoMode::Translate,
+      center: Point3::new(0.0, 0.0, 0.0),
+      drag: None,
+      rotate: Model::from_builder(device, layout, &rotate_handles(), &AXIS_MATERIALS),
+      scale: Model::from_builder(device, layout, &arrow_handles("Scale Gizmo", 0.12, 0.12), &AXIS_MATERIALS),
+      translate: Model::from_builder(device, layout, &arrow_handles("Translate Gizmo", 0.25, 0.08), &AXIS_MATERIALS),
+    }
+  }
+
+  /// Starts dragging if the ray hits one of the handles of the current mode.
+  /// `pivot` is the point in the target's local space that rotation and
+  /// scale keep fixed.
+  pub fn begin_drag(&mut self, ray: &Ray, target: Transform, pivot: Point3<f32>) -> bool {
+    let axis = match self.hit(ray) {
+      Some(axis) => axis,
+      None => return false,
+    };
+    let direction = match self.mode {
+      GizmoMode::Scale => target.rotation * axis_vector(axis),
+      _ => axis_vector(axis),
+    };
+
+    self.drag = Some(Drag {
+      axis,
+      center: self.center,
+      direction,
+      pivot,
+      start: target,
+      start_parameter: axis_parameter(ray, self.center, direction).unwrap_or(0.0),
+      start_vector: plane_vector(ray, self.center, direction).unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0)),
+    });
+
+    true
+  }
+
+  /// New transform for the dragged target given the current cursor ray.
+  pub fn drag(&self, ray: &Ray) -> Option<Transform> {
+    let drag = self.drag.as_ref()?;
+    let direction = drag.direction;
+    let mut transform = drag.start;
+
+    match self.mode {
+      GizmoMode::Translate => {
+        let parameter = axis_parameter(ray, drag.center, direction)?;
+
+        transform.position += direction * (parameter - drag.start_parameter);
+      }
+      GizmoMode::Rotate => {
+        let vector = plane_vector(ray, drag.center, direction)?;
+        let angle = direction.dot(drag.start_vector.cross(vector)).atan2(drag.start_vector.dot(vector));
+
+        transform.rotation = Quaternion::from_axis_angle(direction, Rad(angle)) * drag.start.rotation;
+      }
+      GizmoMode::Scale => {
+        let parameter = axis_parameter(ray, drag.center, direction)?;
+
+        if drag.start_parameter.abs() < PARALLEL_EPSILON {
+          return None;
+        }
+
+        let ratio = (parameter / drag.start_parameter).max(MIN_SCALE);
+
+        transform.scale[drag.axis] = (drag.start.scale[drag.axis] * ratio).max(MIN_SCALE);
+      }
+    }
+
+    if self.mode != GizmoMode::Translate {
+      transform.position += drag.start.transform_point(drag.pivot) - transform.transform_point(drag.pivot);
+    }
+
+    Some(transform)
+  }
+
+  pub fn end_drag(&mut self) {
+    self.drag = None;
+  }
+
+  /// Axis of the handle under the ray, if any.
+  pub fn hit(&self, ray: &Ray) -> Option<usize> {
+    let model = self.model();
+
+    ray::cast(std::slice::from_ref(model), ray).map(|hit| model.meshes[hit.mesh].material)
+  }
+
+  pub fn is_dragging(&self) -> bool {
+    self.drag.is_some()
+  }
+
+  pub fn model(&self) -> &Model {
+    match self.mode {
+      GizmoMode::Translate => &self.translate,
+      GizmoMode::Rotate => &self.rotate,
+      GizmoMode::Scale => &self.scale,
+    }
+  }
+
+  /// Moves the handles to `center`, sized so the arms reach `size` units.
+  pub fn place(&mut self, queue: &wgpu::Queue, center: Point3<f32>, size: f32) {
+    let transform = Transform {
+      position: Vector3::new(center.x, center.y, center.z),
+      scale: Vector3::new(size, size, size),
+      ..Default::default()
+    };
+
+    self.center = center;
+    self.rotate.set_transform(queue, transform);
+    self.scale.set_transform(queue, transform);
+    self.translate.set_transform(queue, transform);
+  }
+
+  pub fn set_mode(&mut self, mode: GizmoMode) {
+    if self.drag.is_none() {
+      self.mode = mode;
+    }
+  }
+}
+
+fn arrow_handles(label: &str, head_length: f32, head_thickness: f32) -> MeshBuilder {
+  let mut builder = MeshBuilder::new(label);
+
+  for axis in 0..AXIS_MATERIALS.len() {
+    builder.set_material(axis);
+    add_axis_box(&mut builder, axis, SHAFT_START, 1.0 - head_length, SHAFT_THICKNESS);
+    add_axis_box(&mut builder, axis, 1.0 - head_length, 1.0, head_thickness);
+  }
+
+  builder
+}
+
+fn rotate_handles() -> MeshBuilder {
+  let mut builder = MeshBuilder::new("Rotate Gizmo");
+  let size = Vector2::new(SHAFT_THICKNESS, SHAFT_THICKNESS);
+
+  for axis in 0..AXIS_MATERIALS.len() {
+    let direction = axis_vector(axis);
+    let u = axis_vector((axis + 1) % 3);
+    let v = axis_vector((axis + 2) % 3);
+
+    builder.set_material(axis);
+
+    // A ring of short beams, so it is solid from any angle.
+    for segment in 0..RING_SEGMENTS {
+      let a0 = 2.0 * PI * segment as f32 / RING_SEGMENTS as f32;
+      let a1 = 2.0 * PI * (segment + 1) as f32 / RING_SEGMENTS as f32;
+
+      builder.add_beam(u * a0.cos() + v * a0.sin(), u * a1.cos() + v * a1.sin(), direction, size);
+    }
+  }
+
+  builder
+}
+
+fn add_axis_box(builder: &mut MeshBuilder, axis: usize, start: f32, end: f32, thickness: f32) {
+  let mut near_corner = Vector3::new(-thickness / 2.0, -thickness / 2.0, -thickness / 2.0);
+  let mut size = Vector3::new(thickness, thickness, thickness);
+
+  near_corner[axis] = start;
+  size[axis] = end - start;
+
+  builder.add_box(near_corner, size);
+}
+
+fn axis_vector(axis: usize) -> Vector3<f32> {
+  let mut vector = Vector3::new(0.0, 0.0, 0.0);
+
+  vector[axis] = 1.0;
+  vector
+}
+
+/// Parameter along the axis line through `center` of the point closest to
+/// the ray, or `None` when the ray runs parallel to the axis.
+fn axis_parameter(ray: &Ray, center: Point3<f32>, axis: Vector3<f32>) -> Option<f32> {
+  let offset = center - ray.origin;
+  let b = axis.dot(ray.direction);
+  let d = axis.dot(offset);
+  let e = ray.direction.dot(offset);
+  let denominator = 1.0 - b * b;
+
+  if denominator.abs() < PARALLEL_EPSILON {
+    return None;
+  }
+
+  Some((b * e - d) / denominator)
+}
+
+/// Offset from `center` of the point where the ray crosses the plane through
+/// `center` perpendicular to the axis.
+fn plane_vector(ray: &Ray, center: Point3<f32>, axis: Vector3<f32>) -> Option<Vector3<f32>> {
+  let denominator = ray.direction.dot(axis);
+
+  if denominator.abs() < PARALLEL_EPSILON {
+    return None;
+  }
+
+  let distance = (center - ray.origin).dot(axis) / denominator;
+
+  if distance < 0.0 {
+    return None;
+  }
+
+  Some(ray.at(distance) - center)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn handles_are_valid() {
+    for handles in [rotate_handles(), arrow_handles("Scale Gizmo", 0.12, 0.12), arrow_handles("Translate Gizmo", 0.25, 0.08)] {
+      let report = handles.validate();
+
+      assert!(report.is_valid(), "{}", report);
+    }
+  }
+}
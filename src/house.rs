@@ -0,0 +1,612 @@
+use anyhow::{bail, Result};
+use cgmath::{InnerSpace, Rad, Vector2, Vector3};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use crate::{
+  mesh::MeshBuilder,
+  skeleton::{self, signed_area},
+};
+
+pub const HOUSE_WALL_MATERIAL: usize = 0;
+pub const HOUSE_ROOF_MATERIAL: usize = 1;
+pub const HOUSE_FRAME_MATERIAL: usize = 2;
+pub const HOUSE_FLOOR_MATERIAL: usize = 3;
+pub const HOUSE_MATERIALS: [(&str, [f32;4]); 4] = [
+  ("Wall", WALL_COLOR),
+  ("Roof", ROOF_COLOR),
+  ("Frame", FRAME_COLOR),
+  ("Floor", FLOOR_COLOR),
+];
+
+const FLAT_ROOF_THICKNESS: f32 = 0.08;
+const FLOOR_COLOR: [f32;4] = [0.55, 0.4, 0.3, 1.0];
+const FLOOR_THICKNESS: f32 = 0.04;
+/// How far frames stand out from the outside of the wall.
+const FRAME_DEPTH: f32 = 0.02;
+const FRAME_COLOR: [f32;4] = [0.95, 0.95, 0.92, 1.0];
+/// Height of the steep lower slope of a mansard roof, as a fraction of the
+/// floor height.
+const MANSARD_BREAK: f32 = 0.8;
+const MANSARD_LOWER_PITCH: f32 = 70.0;
+const ROOF_COLOR: [f32;4] = [0.45, 0.2, 0.15, 1.0];
+const SNAP_EPSILON: f32 = 1e-5;
+const WALL_COLOR: [f32;4] = [0.85, 0.8, 0.7, 1.0];
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum RoofStyle {
+  /// Two slopes meeting at a ridge along the length, with triangular
+  /// gable walls at the ends.
+  Gable,
+  /// Slopes on all four sides meeting at a ridge along the longer side.
+  Hip,
+  /// Flat slab over the walls. Pitch is ignored.
+  Flat,
+  /// One slope rising across the width.
+  Shed,
+  /// Steep lower slopes on all four sides under a hip roof at the pitch.
+  Mansard,
+}
+
+/// Everything that shapes a generated house. Lengths are in world units
+/// and the house stands on the origin with its front wall facing -Z.
+#[derive(Copy, Clone, Debug)]
+pub struct HouseSettings {
+  pub width: f32,
+  pub length: f32,
+  pub floors: u32,
+  pub floor_height: f32,
+  pub wall_thickness: f32,
+  pub roof: RoofStyle,
+  pub pitch: Rad<f32>,
+  /// How far the roof reaches past the walls.
+  pub overhang: f32,
+  /// The door is centered in the front wall. A zero width leaves it out.
+  pub door_width: f32,
+  pub door_height: f32,
+  pub window_width: f32,
+  pub window_height: f32,
+  /// Height of the bottom of each window above its floor.
+  pub window_sill: f32,
+  /// Each wall gets one window per floor for every `window_spacing` of its
+  /// length. Windows that would overlap the door are left out.
+  pub window_spacing: f32,
+  pub frame_width: f32,
+}
+
+impl Default for HouseSettings {
+  fn default() -> Self {
+    Self {
+      width: 1.0,
+      length: 1.0,
+      floors: 1,
+      floor_height: 1.0,
+      wall_thickness: 0.05,
+      roof: RoofStyle::Gable,
+      pitch: Rad(30.0_f32.to_radians()),
+      overhang: 0.2,
+      door_width: 0.2,
+      door_height: 0.55,
+      window_width: 0.18,
+      window_height: 0.25,
+      window_sill: 0.35,
+      window_spacing: 0.5,
+      frame_width: 0.02,
+    }
+  }
+}
+
+impl HouseSettings {
+  /// Height of the top of the walls.
+  pub fn wall_height(&self) -> f32 {
+    self.floors.max(1) as f32 * self.floor_height
+  }
+}
+
+/// Rectangle cut out of a wall, in the wall's own coordinates.
+#[derive(Copy, Clone, Debug)]
+struct Opening {
+  u0: f32,
+  u1: f32,
+  v0: f32,
+  v1: f32,
+  door: bool,
+}
+
+impl Opening {
+  fn contains(&self, u: f32, v: f32) -> bool {
+    u > self.u0 && u < self.u1 && v > self.v0 && v < self.v1
+  }
+
+  /// Whether the openings, frames included, overlap.
+  fn overlaps(&self, other: &Opening, frame: f32) -> bool {
+    self.u0 - frame < other.u1 + frame && other.u0 - frame < self.u1 + frame
+      && self.v0 - frame < other.v1 + frame && other.v0 - frame < self.v1 + frame
+  }
+}
+
+/// One side of the house. `u` runs along the outside from `origin`, `v` is
+/// height and depth goes into the wall from its outer face.
+struct Wall {
+  origin: Vector3<f32>,
+  along: Vector3<f32>,
+  outward: Vector3<f32>,
+  length: f32,
+}
+
+impl Wall {
+  fn point(&self, u: f32, v: f32, depth: f32) -> Vector3<f32> {
+    self.origin + self.along * u + Vector3::unit_y() * v - self.outward * depth
+  }
+}
+
+/// Geometry of the house on three material slots: walls and floors, roof,
+/// and door and window frames.
+pub fn build(settings: &HouseSettings) -> MeshBuilder {
+  let mut builder = MeshBuilder::new("House");
+  let half_width = settings.width / 2.0;
+  let half_length = settings.length / 2.0;
+  let walls = [
+    Wall {
+      origin: Vector3::new(-half_width, 0.0, -half_length),
+      along: Vector3::unit_x(),
+      outward: -Vector3::unit_z(),
+      length: settings.width,
+    },
+    Wall {
+      origin: Vector3::new(half_width, 0.0, -half_length),
+      along: Vector3::unit_z(),
+      outward: Vector3::unit_x(),
+      length: settings.length,
+    },
+    Wall {
+      origin: Vector3::new(half_width, 0.0, half_length),
+      along: -Vector3::unit_x(),
+      outward: Vector3::unit_z(),
+      length: settings.width,
+    },
+    Wall {
+      origin: Vector3::new(-half_width, 0.0, half_length),
+      along: -Vector3::unit_z(),
+      outward: -Vector3::unit_x(),
+      length: settings.length,
+    },
+  ];
+
+  for (index, wall) in walls.iter().enumerate() {
+    let openings = wall_openings(settings, wall, index == 0);
+
+    builder.set_material(HOUSE_WALL_MATERIAL);
+    add_wall(&mut builder, settings, wall, &openings);
+    add_gable_fill(&mut builder, settings, wall);
+
+    builder.set_material(HOUSE_FRAME_MATERIAL);
+
+    for opening in &openings {
+      add_frame(&mut builder, settings, wall, opening);
+    }
+  }
+
+  builder.set_material(HOUSE_FLOOR_MATERIAL);
+  add_floors(&mut builder, settings);
+
+  builder.set_material(HOUSE_ROOF_MATERIAL);
+  add_roof(&mut builder, settings);
+
+  builder
+}
+
+/// Footprint of a building on the XZ plane, as read from a JSON file such
+/// as `{ "footprint": [[0, 0], [4, 0], [4, 2], [0, 2]] }`.
+#[derive(Deserialize)]
+struct FootprintFile {
+  footprint: Vec<[f32;2]>,
+}
+
+/// Reads the corners of a footprint from a JSON file. The corners can run
+/// either way around but the outline must not cross itself.
+pub fn load_footprint<P: AsRef<Path>>(path: P) -> Result<Vec<Vector2<f32>>> {
+  let file: FootprintFile = serde_json::from_str(&fs::read_to_string(path.as_ref())?)?;
+  let mut footprint = file.footprint.iter().map(|&[x, z]| Vector2::new(x, z)).collect::<Vec<_>>();
+
+  footprint.dedup_by(|a, b| (*a - *b).magnitude() < SNAP_EPSILON);
+
+  if footprint.len() > 1 && (footprint[0] - footprint[footprint.len() - 1]).magnitude() < SNAP_EPSILON {
+    footprint.pop();
+  }
+
+  if footprint.len() < 3 || signed_area(&footprint).abs() < SNAP_EPSILON {
+    bail!("{} does not outline an area", path.as_ref().display());
+  }
+
+  Ok(footprint)
+}
+
+/// Walls extruded from any footprint under a hip roof traced along its
+/// straight skeleton, so every wall gets a slope at the pitch. The roof
+/// style, floor slabs, doors and windows of the settings are not used.
+pub fn build_footprint(footprint: &[Vector2<f32>], settings: &HouseSettings) -> MeshBuilder {
+  let mut builder = MeshBuilder::new("Building");
+  let mut footprint = footprint.to_vec();
+
+  if signed_area(&footprint) < 0.0 {
+    footprint.reverse();
+  }
+
+  let height = settings.wall_height();
+  let thickness = settings.wall_thickness;
+  let point = |position: Vector2<f32>, y: f32| Vector3::new(position.x, y, position.y);
+  let inside = skeleton::offset(&footprint, thickness);
+
+  builder.set_material(HOUSE_WALL_MATERIAL);
+
+  for (i, &a) in footprint.iter().enumerate() {
+    let b = footprint[(i + 1) % footprint.len()];
+    let along = (b - a).normalize();
+    let outward = Vector3::new(along.y, 0.0, -along.x);
+    let (inner_a, inner_b) = (inside[i], inside[(i + 1) % inside.len()]);
+
+    builder.add_polygon(&[point(a, 0.0), point(b, 0.0), point(b, height), point(a, height)], outward);
+    builder.add_polygon(&[
+      point(inner_a, 0.0),
+      point(inner_b, 0.0),
+      point(inner_b, height),
+      point(inner_a, height),
+    ], -outward);
+    builder.add_polygon(&[
+      point(a, height),
+      point(b, height),
+      point(inner_b, height),
+      point(inner_a, height),
+    ], Vector3::unit_y());
+  }
+
+  builder.set_material(HOUSE_ROOF_MATERIAL);
+
+  let slope = settings.pitch.0.tan();
+  let eave = height - settings.overhang * slope;
+
+  // The flat underside at the eaves closes the roof and is the ceiling
+  // inside the walls.
+  for quad in skeleton::sweep(&footprint, -settings.overhang) {
+    let raised = quad.map(|corner| Vector3::new(corner.x, height + corner.y * slope, corner.z));
+    let flat = quad.map(|corner| Vector3::new(corner.x, eave, corner.z));
+
+    builder.add_polygon(&raised, Vector3::unit_y());
+    builder.add_polygon(&flat, -Vector3::unit_y());
+  }
+
+  builder
+}
+
+fn wall_openings(settings: &HouseSettings, wall: &Wall, front: bool) -> Vec<Opening> {
+  let mut openings = Vec::new();
+  let frame = settings.frame_width;
+  let margin = settings.wall_thickness + frame;
+
+  if front && settings.door_width > 0.0 && settings.door_height > 0.0 {
+    openings.push(Opening {
+      u0: (wall.length - settings.door_width) / 2.0,
+      u1: (wall.length + settings.door_width) / 2.0,
+      v0: 0.0,
+      v1: settings.door_height.min(settings.floor_height - frame),
+      door: true,
+    });
+  }
+
+  if settings.window_width <= 0.0 || settings.window_height <= 0.0 || settings.window_spacing <= 0.0 {
+    return openings;
+  }
+
+  let count = (wall.length / settings.window_spacing).floor() as u32;
+
+  for floor in 0..settings.floors {
+    for i in 0..count {
+      let center = (i as f32 + 0.5) * wall.length / count as f32;
+      let v0 = floor as f32 * settings.floor_height + settings.window_sill;
+      let window = Opening {
+        u0: center - settings.window_width / 2.0,
+        u1: center + settings.window_width / 2.0,
+        v0,
+        v1: v0 + settings.window_height,
+        door: false,
+      };
+      let fits = window.u0 >= margin
+        && window.u1 <= wall.length - margin
+        && window.v1 + frame <= (floor + 1) as f32 * settings.floor_height;
+
+      if fits && !openings.iter().any(|other| window.overlaps(other, frame)) {
+        openings.push(window);
+      }
+    }
+  }
+
+  openings
+}
+
+/// Outer and inner faces of the wall around its openings, and the reveals
+/// that line each opening through the thickness of the wall.
+fn add_wall(builder: &mut MeshBuilder, settings: &HouseSettings, wall: &Wall, openings: &[Opening]) {
+  let thickness = settings.wall_thickness;
+  let height = settings.wall_height();
+
+  add_wall_face(builder, wall, (0.0, wall.length), height, 0.0, openings, wall.outward);
+  add_wall_face(builder, wall, (thickness, wall.length - thickness), height, thickness, openings, -wall.outward);
+  // Each wall caps the corner it starts at, so the caps do not overlap.
+  builder.add_polygon(&[
+    wall.point(0.0, height, 0.0),
+    wall.point(wall.length - thickness, height, 0.0),
+    wall.point(wall.length - thickness, height, thickness),
+    wall.point(0.0, height, thickness),
+  ], Vector3::unit_y());
+
+  // Reveals reach out through the frame, which only rings the opening.
+  let front = if settings.frame_width > 0.0 { -FRAME_DEPTH } else { 0.0 };
+
+  for opening in openings {
+    let corners = [
+      wall.point(opening.u0, opening.v0, front),
+      wall.point(opening.u1, opening.v0, front),
+      wall.point(opening.u1, opening.v1, front),
+      wall.point(opening.u0, opening.v1, front),
+    ];
+    let depth = -wall.outward * (thickness - front);
+
+    builder.add_polygon(&[corners[0], corners[3], corners[3] + depth, corners[0] + depth], wall.along);
+    builder.add_polygon(&[corners[1], corners[2], corners[2] + depth, corners[1] + depth], -wall.along);
+    builder.add_polygon(&[corners[0], corners[1], corners[1] + depth, corners[0] + depth], Vector3::unit_y());
+    builder.add_polygon(&[corners[3], corners[2], corners[2] + depth, corners[3] + depth], -Vector3::unit_y());
+  }
+}
+
+/// Splits the face along every opening edge and keeps the cells that are
+/// not inside an opening.
+fn add_wall_face(
+  builder: &mut MeshBuilder,
+  wall: &Wall,
+  (start, end): (f32, f32),
+  height: f32,
+  depth: f32,
+  openings: &[Opening],
+  facing: Vector3<f32>,
+) {
+  let mut us = vec![start, end];
+  let mut vs = vec![0.0, height];
+
+  for opening in openings {
+    us.extend([opening.u0, opening.u1].iter().map(|u| u.clamp(start, end)));
+    vs.extend([opening.v0, opening.v1].iter().map(|v| v.clamp(0.0, height)));
+  }
+
+  for breaks in [&mut us, &mut vs] {
+    breaks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    breaks.dedup_by(|a, b| (*a - *b).abs() < SNAP_EPSILON);
+  }
+
+  for v in vs.windows(2) {
+    let v_mid = (v[0] + v[1]) / 2.0;
+    let mut run_start = None;
+
+    // Cells in a row between two openings are joined into one quad.
+    for (i, &u) in us.iter().enumerate() {
+      let open = match us.get(i + 1) {
+        Some(&next) => openings.iter().any(|opening| opening.contains((u + next) / 2.0, v_mid)),
+        None => true,
+      };
+
+      match (run_start, open) {
+        (None, false) => run_start = Some(u),
+        (Some(start), true) => {
+          builder.add_polygon(&[
+            wall.point(start, v[0], depth),
+            wall.point(u, v[0], depth),
+            wall.point(u, v[1], depth),
+            wall.point(start, v[1], depth),
+          ], facing);
+          run_start = None;
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+/// Fills the gap between the top of the wall and the underside of a gable
+/// or shed roof. Only the outside is filled, as the ceiling hides the attic.
+fn add_gable_fill(builder: &mut MeshBuilder, settings: &HouseSettings, wall: &Wall) {
+  let height = settings.wall_height();
+  let top = |u: f32| {
+    let point = wall.point(u, height, 0.0);
+
+    point + Vector3::unit_y() * roof_rise(settings, point)
+  };
+  let mut points = vec![wall.point(0.0, height, 0.0), wall.point(wall.length, height, 0.0), top(wall.length)];
+  let middle = top(wall.length / 2.0);
+  let last = top(0.0);
+
+  // Only a ridge above the middle of the wall bends the top edge.
+  if (middle - points[2]).cross(last - points[2]).magnitude() > SNAP_EPSILON {
+    points.push(middle);
+  }
+  points.push(last);
+  points.retain(|point| point.y >= height - SNAP_EPSILON);
+
+  if points.iter().any(|point| point.y > height + SNAP_EPSILON) {
+    builder.add_polygon(&points, wall.outward);
+  }
+}
+
+/// Height of the underside of a gable or shed roof above the top of the
+/// walls, at a point on a wall. Other roofs sit directly on the walls.
+fn roof_rise(settings: &HouseSettings, point: Vector3<f32>) -> f32 {
+  let slope = settings.pitch.0.tan();
+
+  match settings.roof {
+    RoofStyle::Gable => (settings.width / 2.0 - point.x.abs()).max(0.0) * slope,
+    RoofStyle::Shed => (point.x + settings.width / 2.0).max(0.0) * slope,
+    RoofStyle::Hip | RoofStyle::Flat | RoofStyle::Mansard => 0.0,
+  }
+}
+
+/// Ring standing out from the wall around an opening, open at the bottom
+/// of a door. Its inside is lined by the reveals from `add_wall`, and its
+/// back is the wall it stands on.
+fn add_frame(builder: &mut MeshBuilder, settings: &HouseSettings, wall: &Wall, opening: &Opening) {
+  let frame = settings.frame_width;
+
+  if frame <= 0.0 {
+    return;
+  }
+
+  let bottom = if opening.door { opening.v0 } else { opening.v0 - frame };
+  let outer = [
+    (opening.u0 - frame, bottom),
+    (opening.u1 + frame, bottom),
+    (opening.u1 + frame, opening.v1 + frame),
+    (opening.u0 - frame, opening.v1 + frame),
+  ];
+  let inner = [
+    (opening.u0, opening.v0),
+    (opening.u1, opening.v0),
+    (opening.u1, opening.v1),
+    (opening.u0, opening.v1),
+  ];
+  let sides = [-Vector3::unit_y(), wall.along, Vector3::unit_y(), -wall.along];
+  let front = |(u, v): (f32, f32)| wall.point(u, v, -FRAME_DEPTH);
+  let back = |(u, v): (f32, f32)| wall.point(u, v, 0.0);
+
+  for (i, &side) in sides.iter().enumerate() {
+    let next = (i + 1) % 4;
+
+    // A door has no bar across its bottom, so the jambs end on the ground.
+    if opening.door && i == 0 {
+      builder.add_polygon(&[front(outer[0]), front(inner[0]), back(inner[0]), back(outer[0])], side);
+      builder.add_polygon(&[front(inner[1]), front(outer[1]), back(outer[1]), back(inner[1])], side);
+      continue;
+    }
+
+    builder.add_polygon(&[front(outer[i]), front(outer[next]), front(inner[next]), front(inner[i])], wall.outward);
+    builder.add_polygon(&[front(outer[i]), front(outer[next]), back(outer[next]), back(outer[i])], side);
+  }
+}
+
+/// Floor of every storey and the ceiling under the roof, inside the walls.
+fn add_floors(builder: &mut MeshBuilder, settings: &HouseSettings) {
+  let x = settings.width / 2.0 - settings.wall_thickness;
+  let z = settings.length / 2.0 - settings.wall_thickness;
+  let rectangle = |y: f32| [
+    Vector3::new(-x, y, -z),
+    Vector3::new(x, y, -z),
+    Vector3::new(x, y, z),
+    Vector3::new(-x, y, z),
+  ];
+
+  for floor in 0..settings.floors.max(1) {
+    let y = floor as f32 * settings.floor_height;
+
+    builder.add_polygon(&rectangle(y), Vector3::unit_y());
+
+    if floor > 0 {
+      builder.add_polygon(&rectangle(y - FLOOR_THICKNESS), -Vector3::unit_y());
+    }
+  }
+
+  // A flat roof is a slab whose underside is already the ceiling.
+  if settings.roof != RoofStyle::Flat {
+    builder.add_polygon(&rectangle(settings.wall_height()), -Vector3::unit_y());
+  }
+}
+
+/// Roof faces only face up. The ceiling from `add_floors` closes the house
+/// under them, which leaves just the underside of the eaves open.
+fn add_roof(builder: &mut MeshBuilder, settings: &HouseSettings) {
+  let height = settings.wall_height();
+  let overhang = settings.overhang;
+  let x = settings.width / 2.0 + overhang;
+  let z = settings.length / 2.0 + overhang;
+  let slope = settings.pitch.0.tan();
+  let eave = height - overhang * slope;
+
+  match settings.roof {
+    RoofStyle::Gable => {
+      let ridge = height + settings.width / 2.0 * slope;
+
+      for side in [-1.0, 1.0] {
+        builder.add_polygon(&[
+          Vector3::new(side * x, eave, -z),
+          Vector3::new(side * x, eave, z),
+          Vector3::new(0.0, ridge, z),
+          Vector3::new(0.0, ridge, -z),
+        ], Vector3::unit_y());
+      }
+    }
+    RoofStyle::Hip => add_hip(builder, x, z, eave, slope),
+    RoofStyle::Flat => {
+      builder.add_box(
+        Vector3::new(-x, height, -z),
+        Vector3::new(2.0 * x, FLAT_ROOF_THICKNESS, 2.0 * z),
+      );
+    }
+    RoofStyle::Shed => {
+      let low = height - overhang * slope;
+      let high = height + (settings.width + overhang) * slope;
+
+      builder.add_polygon(&[
+        Vector3::new(-x, low, -z),
+        Vector3::new(x, high, -z),
+        Vector3::new(x, high, z),
+        Vector3::new(-x, low, z),
+      ], Vector3::unit_y());
+    }
+    RoofStyle::Mansard => {
+      let lower_slope = MANSARD_LOWER_PITCH.to_radians().tan();
+      let rise = settings.floor_height * MANSARD_BREAK;
+      let inset = rise / lower_slope;
+      let bottom = height - overhang * lower_slope;
+      let top = height + rise;
+      let inner_x = (settings.width / 2.0 - inset).max(SNAP_EPSILON);
+      let inner_z = (settings.length / 2.0 - inset).max(SNAP_EPSILON);
+      let outer = [(-x, -z), (x, -z), (x, z), (-x, z)];
+      let inner = [(-inner_x, -inner_z), (inner_x, -inner_z), (inner_x, inner_z), (-inner_x, inner_z)];
+
+      for i in 0..4 {
+        let j = (i + 1) % 4;
+
+        builder.add_polygon(&[
+          Vector3::new(outer[i].0, bottom, outer[i].1),
+          Vector3::new(outer[j].0, bottom, outer[j].1),
+          Vector3::new(inner[j].0, top, inner[j].1),
+          Vector3::new(inner[i].0, top, inner[i].1),
+        ], Vector3::unit_y());
+      }
+
+      add_hip(builder, inner_x, inner_z, top, slope);
+    }
+  }
+}
+
+/// Hip roof over the rectangle reaching `x` and `z` either side of the
+/// origin, with its eaves at `eave`.
+fn add_hip(builder: &mut MeshBuilder, x: f32, z: f32, eave: f32, slope: f32) {
+  // Work with the ridge along the second axis and swap back if the house
+  // is wider than it is long.
+  let swap = x > z;
+  let (a, b) = if swap { (z, x) } else { (x, z) };
+  let point = |u: f32, y: f32, w: f32| if swap { Vector3::new(w, y, u) } else { Vector3::new(u, y, w) };
+  let ridge = eave + a * slope;
+  let ridge_end = b - a;
+
+  for side in [-1.0, 1.0] {
+    builder.add_polygon(&[
+      point(side * a, eave, -b),
+      point(side * a, eave, b),
+      point(0.0, ridge, ridge_end),
+      point(0.0, ridge, -ridge_end),
+    ], Vector3::unit_y());
+    builder.add_polygon(&[
+      point(-a, eave, side * b),
+      point(a, eave, side * b),
+      point(0.0, ridge, side * ridge_end),
+    ], Vector3::unit_y());
+  }
+}
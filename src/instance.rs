@@ -1,23 +1,53 @@
-use cgmath::{Matrix3, Matrix4, Quaternion, Vector3};
+use cgmath::{Matrix4, One, Quaternion, Vector3};
 
-use crate::mesh;
+use crate::{
+    mesh,
+    transform::{normal_matrix, Transform},
+};
 
+#[derive(Copy, Clone, Debug)]
 pub struct Instance {
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
 }
 
-impl Instance {
-    pub fn to_raw(&self) -> InstanceRaw {
-        let model = Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation);
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
 
-        InstanceRaw {
-            model: model.into(),
-            normal: Matrix3::from(self.rotation).into(),
+impl From<Transform> for Instance {
+    fn from(transform: Transform) -> Self {
+        Self {
+            position: transform.position,
+            rotation: transform.rotation,
+            scale: transform.scale,
         }
     }
 }
 
+impl Instance {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Raw instance data for a copy placed relative to `parent`, usually the
+    /// transform of the model the instance belongs to. The normal matrix is
+    /// the inverse transpose of the result, so stretched copies stay lit
+    /// correctly.
+    pub fn to_raw(self, parent: Matrix4<f32>) -> InstanceRaw {
+        InstanceRaw::from_matrix(parent * self.matrix())
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
@@ -25,6 +55,15 @@ pub struct InstanceRaw {
     normal: [[f32; 3]; 3],
 }
 
+impl InstanceRaw {
+    pub fn from_matrix(model: Matrix4<f32>) -> Self {
+        Self {
+            model: model.into(),
+            normal: normal_matrix(model).into(),
+        }
+    }
+}
+
 impl mesh::Vertex for InstanceRaw {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
@@ -0,0 +1,384 @@
+use cgmath::{Deg, InnerSpace, One, Quaternion, Rad, Rotation, Rotation3, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+  mesh::{MeshBuilder, COLOR},
+  validation::weld_key,
+};
+
+pub const PLANT_BARK_MATERIAL: usize = 0;
+pub const PLANT_LEAF_MATERIAL: usize = 1;
+pub const PLANT_MATERIALS: [(&str, [f32;4]); 2] = [("Bark", BARK_COLOR), ("Leaf", LEAF_COLOR)];
+
+const BARK_COLOR: [f32;4] = [0.4, 0.28, 0.18, 1.0];
+/// Sides of the tapered cylinder drawn for each branch segment.
+const BRANCH_SIDES: u32 = 6;
+const LEAF_COLOR: [f32;4] = [0.25, 0.55, 0.2, 1.0];
+/// Vertex color of the upper side of leaves, a little darker than the
+/// underside as on real leaves.
+const LEAF_TOP_SHADE: [f32;4] = [0.8, 0.85, 0.8, 1.0];
+/// Thickness of a leaf as a fraction of its length.
+const LEAF_THICKNESS: f32 = 0.02;
+/// Expansion stops early once the string grows past this many symbols.
+const MAX_SYMBOLS: usize = 1_000_000;
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum PlantPreset {
+  /// Bushy plant with leaves all along its branches.
+  Bush,
+  /// Flat fractal fern.
+  Fern,
+  /// Trunk splitting into three branches at every level.
+  Tree,
+  /// Weed that grows a little differently for every seed.
+  Weed,
+}
+
+/// Rewrites `symbol` to `replacement`. When several rules share a symbol,
+/// one is picked at random for each occurrence with odds set by `weight`.
+#[derive(Clone, Debug)]
+pub struct Rule {
+  pub symbol: char,
+  pub weight: f32,
+  pub replacement: String,
+}
+
+impl Rule {
+  pub fn new(symbol: char, replacement: &str) -> Self {
+    Self::weighted(symbol, 1.0, replacement)
+  }
+
+  pub fn weighted(symbol: char, weight: f32, replacement: &str) -> Self {
+    Self {
+      symbol,
+      weight,
+      replacement: replacement.to_string(),
+    }
+  }
+}
+
+/// An L-system and the turtle that draws it. The turtle starts at the
+/// origin heading up and reads:
+///
+/// - `F` draw a branch segment forward, `f` move forward without drawing
+/// - `+` `-` turn left and right, `&` `^` pitch down and up, `\` `/` roll
+///   left and right, all by `angle`, and `|` turn around
+/// - `[` `]` save and restore the turtle
+/// - `!` thin the branches by `thinning`
+/// - `L` draw a leaf
+///
+/// Any other symbol only takes part in the rewriting.
+#[derive(Clone, Debug)]
+pub struct LSystem {
+  pub axiom: String,
+  pub rules: Vec<Rule>,
+  pub iterations: u32,
+  pub angle: Rad<f32>,
+  /// Length of a branch segment.
+  pub length: f32,
+  /// Radius of the first branch segment.
+  pub radius: f32,
+  /// Radius at the end of a segment as a fraction of its start.
+  pub taper: f32,
+  pub thinning: f32,
+  pub leaf_size: f32,
+}
+
+impl LSystem {
+  pub fn preset(preset: PlantPreset) -> Self {
+    match preset {
+      PlantPreset::Bush => Self {
+        axiom: "A".to_string(),
+        rules: vec![
+          Rule::new('A', "[&FL!A]/////[&FL!A]///////[&FL!A]"),
+          Rule::new('F', "S/////F"),
+          Rule::new('S', "FL"),
+        ],
+        iterations: 6,
+        angle: Deg(22.5).into(),
+        length: 0.1,
+        radius: 0.03,
+        taper: 0.95,
+        thinning: 0.7,
+        leaf_size: 0.08,
+      },
+      PlantPreset::Fern => Self {
+        axiom: "X".to_string(),
+        rules: vec![
+          Rule::new('X', "F+[[XL]-XL]-F[-FXL]+XL"),
+          Rule::new('F', "FF"),
+        ],
+        iterations: 5,
+        angle: Deg(25.0).into(),
+        length: 0.02,
+        radius: 0.006,
+        taper: 0.98,
+        thinning: 0.7,
+        leaf_size: 0.03,
+      },
+      PlantPreset::Tree => Self {
+        axiom: "FFA".to_string(),
+        rules: vec![Rule::new('A', "!F[&FLA]////[&FLA]////[&FLA]")],
+        iterations: 6,
+        angle: Deg(30.0).into(),
+        length: 0.4,
+        radius: 0.08,
+        taper: 0.9,
+        thinning: 0.7,
+        leaf_size: 0.15,
+      },
+      PlantPreset::Weed => Self {
+        axiom: "F".to_string(),
+        rules: vec![
+          Rule::weighted('F', 1.0, "F[+F]F[-F]F"),
+          Rule::weighted('F', 1.0, "F[+FL]F"),
+          Rule::weighted('F', 1.0, "F[-FL]F"),
+        ],
+        iterations: 4,
+        angle: Deg(25.7).into(),
+        length: 0.03,
+        radius: 0.005,
+        taper: 0.97,
+        thinning: 0.7,
+        leaf_size: 0.04,
+      },
+    }
+  }
+
+  /// Branches and leaves drawn by the turtle, on their own material slots.
+  /// Rules such as the fern's retrace their steps, so a segment or leaf
+  /// drawn again in the same place is left out.
+  pub fn build(&self, seed: u64) -> MeshBuilder {
+    let mut builder = MeshBuilder::new("Plant");
+    let mut turtle = Turtle {
+      position: Vector3::new(0.0, 0.0, 0.0),
+      rotation: Quaternion::one(),
+      radius: self.radius,
+    };
+    let mut stack = Vec::new();
+    let mut segments = Vec::new();
+    let mut drawn = HashSet::new();
+    let mut leaves = HashSet::new();
+
+    builder.set_material(PLANT_LEAF_MATERIAL);
+
+    for symbol in self.expand(seed).chars() {
+      match symbol {
+        'F' => {
+          let end = turtle.position + turtle.heading() * self.length;
+          let radius = turtle.radius * self.taper;
+
+          if drawn.insert((weld_key(turtle.position), weld_key(end))) {
+            segments.push(Segment {
+              start: Ring { center: turtle.position, rotation: turtle.rotation, radius: turtle.radius },
+              end: Ring { center: end, rotation: turtle.rotation, radius },
+            });
+          }
+
+          turtle.position = end;
+          turtle.radius = radius;
+        }
+        'f' => turtle.position += turtle.heading() * self.length,
+        '+' => turtle.turn(Vector3::unit_z(), self.angle),
+        '-' => turtle.turn(Vector3::unit_z(), -self.angle),
+        '&' => turtle.turn(Vector3::unit_x(), self.angle),
+        '^' => turtle.turn(Vector3::unit_x(), -self.angle),
+        '\\' => turtle.turn(Vector3::unit_y(), self.angle),
+        '/' => turtle.turn(Vector3::unit_y(), -self.angle),
+        '|' => turtle.turn(Vector3::unit_z(), Rad(std::f32::consts::PI)),
+        '[' => stack.push(turtle),
+        ']' => turtle = stack.pop().unwrap_or(turtle),
+        '!' => turtle.radius *= self.thinning,
+        'L' if leaves.insert((weld_key(turtle.position), weld_key(turtle.heading()), weld_key(turtle.left()))) => {
+          add_leaf(&mut builder, &turtle, self.leaf_size);
+        }
+        _ => {}
+      }
+    }
+
+    builder.set_material(PLANT_BARK_MATERIAL);
+    add_branches(&mut builder, &segments);
+
+    builder
+  }
+
+  /// Applies the rules to the axiom `iterations` times.
+  pub fn expand(&self, seed: u64) -> String {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut current = self.axiom.clone();
+
+    for _ in 0..self.iterations {
+      if current.len() > MAX_SYMBOLS {
+        break;
+      }
+
+      let mut next = String::with_capacity(current.len() * 2);
+
+      for symbol in current.chars() {
+        let rules = self.rules.iter().filter(|rule| rule.symbol == symbol).collect::<Vec<_>>();
+        let total = rules.iter().map(|rule| rule.weight).sum::<f32>();
+
+        if rules.is_empty() || total <= 0.0 {
+          next.push(symbol);
+          continue;
+        }
+
+        let mut pick = if rules.len() > 1 { rng.gen_range(0.0..total) } else { 0.0 };
+        let rule = rules.iter().find(|rule| {
+          pick -= rule.weight;
+          pick < 0.0
+        }).unwrap_or(&rules[rules.len() - 1]);
+
+        next.push_str(&rule.replacement);
+      }
+
+      current = next;
+    }
+
+    current
+  }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Turtle {
+  position: Vector3<f32>,
+  /// Turns the turtle's own axes, heading along Y with left along X and up
+  /// along Z, into the world.
+  rotation: Quaternion<f32>,
+  radius: f32,
+}
+
+impl Turtle {
+  fn heading(&self) -> Vector3<f32> {
+    self.rotation.rotate_vector(Vector3::unit_y())
+  }
+
+  fn left(&self) -> Vector3<f32> {
+    self.rotation.rotate_vector(Vector3::unit_x())
+  }
+
+  /// Turns about one of the turtle's own axes.
+  fn turn(&mut self, axis: Vector3<f32>, angle: Rad<f32>) {
+    self.rotation = (self.rotation * Quaternion::from_axis_angle(axis, angle)).normalize();
+  }
+}
+
+/// Circle of corners around the axis of a branch.
+#[derive(Copy, Clone, Debug)]
+struct Ring {
+  center: Vector3<f32>,
+  rotation: Quaternion<f32>,
+  radius: f32,
+}
+
+impl Ring {
+  fn corner(&self, i: u32) -> Vector3<f32> {
+    self.center + branch_side(self.rotation, i) * self.radius
+  }
+
+  /// Matches rings whose corners weld together.
+  fn key(&self) -> [(i64, i64, i64); 3] {
+    [weld_key(self.center), weld_key(self.corner(0)), weld_key(self.corner(1))]
+  }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+  start: Ring,
+  end: Ring,
+}
+
+/// Tapered cylinder for every segment. A segment that grows straight on
+/// from another shares the ring between them, and one that grows out of
+/// the end of another is left open there. Every other ring is capped.
+fn add_branches(builder: &mut MeshBuilder, segments: &[Segment]) {
+  let mut uses = HashMap::new();
+  let ends = segments.iter().map(|segment| weld_key(segment.end.center)).collect::<HashSet<_>>();
+
+  for segment in segments {
+    for ring in [segment.start, segment.end] {
+      *uses.entry(ring.key()).or_insert(0) += 1;
+    }
+  }
+
+  for segment in segments {
+    for i in 0..BRANCH_SIDES {
+      let (a, b) = (branch_side(segment.start.rotation, i), branch_side(segment.start.rotation, i + 1));
+
+      builder.add_polygon(&[
+        segment.start.corner(i),
+        segment.start.corner(i + 1),
+        segment.end.corner(i + 1),
+        segment.end.corner(i),
+      ], a + b);
+    }
+
+    let heading = segment.end.center - segment.start.center;
+
+    let start_open = ends.contains(&weld_key(segment.start.center));
+
+    for (ring, facing, open) in [(segment.start, -heading, start_open), (segment.end, heading, false)] {
+      if !open && uses[&ring.key()] == 1 {
+        let points = (0..BRANCH_SIDES).map(|i| ring.corner(i)).collect::<Vec<_>>();
+
+        builder.add_polygon(&points, facing);
+      }
+    }
+  }
+}
+
+/// Diamond shaped leaf growing along the turtle's heading. It bulges a
+/// little either side of its midrib, so it is closed and both of its sides
+/// face out. The side towards the turtle's up is shaded darker.
+fn add_leaf(builder: &mut MeshBuilder, turtle: &Turtle, size: f32) {
+  let base = turtle.position;
+  let tip = base + turtle.heading() * size;
+  let middle = (base + tip) / 2.0;
+  let side = turtle.left() * size / 4.0;
+  let bulge = turtle.left().cross(turtle.heading()) * size * LEAF_THICKNESS / 2.0;
+  let rim = [middle + side, middle + bulge, middle - side, middle - bulge];
+
+  for i in 0..4 {
+    let (a, b) = (rim[i], rim[(i + 1) % 4]);
+    let color = if i < 2 { LEAF_TOP_SHADE } else { COLOR };
+
+    for end in [base, tip] {
+      let outward = (end + a + b) / 3.0 - middle;
+      let (v1, v2) = if (a - end).cross(b - end).dot(outward) < 0.0 { (b, a) } else { (a, b) };
+
+      builder.add_colored_triangle(end, v1, v2, color);
+    }
+  }
+}
+
+/// Direction from the axis of a branch drawn with `rotation` to corner `i`
+/// of its sides.
+fn branch_side(rotation: Quaternion<f32>, i: u32) -> Vector3<f32> {
+  let angle = i as f32 / BRANCH_SIDES as f32 * std::f32::consts::TAU;
+
+  rotation.rotate_vector(Vector3::new(angle.cos(), 0.0, angle.sin()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::mesh_bytes;
+
+  #[test]
+  fn every_preset_is_valid() {
+    for preset in [PlantPreset::Bush, PlantPreset::Fern, PlantPreset::Tree, PlantPreset::Weed] {
+      let report = LSystem::preset(preset).build(1).validate();
+
+      assert!(report.is_valid(), "{:?}: {}", preset, report);
+    }
+  }
+
+  #[test]
+  fn plant_is_the_same_for_a_seed() {
+    let system = LSystem::preset(PlantPreset::Weed);
+
+    assert_eq!(mesh_bytes(&system.build(7)), mesh_bytes(&system.build(7)));
+  }
+}
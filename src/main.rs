@@ -11,72 +11,431 @@ use winit::{
     window::WindowBuilder,
 };
 
+mod bounds;
+mod bvh;
 mod camera;
+mod city;
 mod color;
 mod draw;
+mod erosion;
+mod fence;
+mod gizmo;
+mod house;
 mod instance;
 mod light;
+mod lsystem;
+mod material;
 mod mesh;
 mod model;
+mod modifier;
+mod noise;
 mod projection;
+mod ray;
 mod render;
+mod scatter;
+mod scene;
+mod skeleton;
+mod stairs;
 mod state;
+mod streaming;
+mod terrain;
+#[cfg(test)]
+mod testing;
 mod texture;
+mod transform;
 mod uniform;
+mod validation;
+mod voxel;
 
-use model::ModelPrimitive;
-use state::State;
+use cgmath::{Deg, Matrix4, SquareMatrix, Vector2, Vector3};
+use city::CitySettings;
+use color::ColorScheme;
+use erosion::{ErosionSettings, HydraulicSettings, ThermalSettings};
+use fence::{FenceSettings, FenceStyle};
+use house::{HouseSettings, RoofStyle};
+use lsystem::{LSystem, PlantPreset};
+use mesh::MeshBuilder;
+use model::{terrain_builder, Model, ModelPrimitive};
+use modifier::{InstanceModifier, Jitter};
+use noise::{Fractal, Noise, NoiseKind, NoiseSettings};
+use scatter::ScatterSettings;
+use stairs::{StairKind, StairSettings};
+use streaming::StreamSettings;
+use state::{State, HOUSE_POST_SIZE};
+use terrain::HeightField;
 
 #[derive(Parser, Debug)]
 #[clap(about, author, version)]
 struct Cli {
+    /// Align scattered copies with the surface normal, and copies along a
+    /// curve with the curve
+    #[clap(long)]
+    align: bool,
+    /// Replicate the last model added this many times along the X axis
+    #[clap(long)]
+    array: Option<u32>,
+    /// Split a city into blocks no longer than this
+    #[clap(long, default_value_t = 10.0)]
+    block_size: f32,
+    /// Sediment a surface erosion droplet carries per unit of speed, water
+    /// and drop in height
+    #[clap(long, default_value_t = 4.0)]
+    capacity: f32,
+    /// Width of a streamed terrain chunk
+    #[clap(long, default_value_t = 32.0)]
+    chunk_size: f32,
+    /// Lay out a city of streets and houses this many units wide
+    #[clap(long)]
+    city: Option<f32>,
+    /// Recolor every model by height, normal direction, face or a random
+    /// palette
+    #[clap(arg_enum, long)]
+    colors: Option<ColorScheme>,
+    /// Copies placed along a curve
+    #[clap(long, default_value_t = 10)]
+    copies: u32,
     #[clap(long, default_value_t = 8)]
     count: u32,
     #[clap(short, long)]
     cube: bool,
+    /// Replicate the last model added along a curve through the points in
+    /// a JSON file
+    #[clap(long)]
+    curve: Option<std::path::PathBuf>,
+    /// Scattered copies per unit of surface area
+    #[clap(long, default_value_t = 1.0)]
+    density: f32,
+    /// Height of the front door of a house
+    #[clap(long, default_value_t = 0.55)]
+    door_height: f32,
+    /// Width of the front door of a house, or 0 for no door
+    #[clap(long, default_value_t = 0.2)]
+    door_width: f32,
+    /// Average each square of this many by this many heightmap pixels into
+    /// one grid point
+    #[clap(long, default_value_t = 1)]
+    downsample: u32,
+    /// Erode the surface with this many raindrops
+    #[clap(long)]
+    erosion: Option<u32>,
+    /// Fraction of its water an erosion droplet loses on every step
+    #[clap(long, default_value_t = 0.02)]
+    evaporation: f32,
+    /// Write height, normal and slope maps of each terrain to files
+    /// starting with this prefix
+    #[clap(long)]
+    export: Option<String>,
+    /// JSON file with the points to run a fence through
+    #[clap(long)]
+    fence: Option<std::path::PathBuf>,
+    /// Height of a fence
+    #[clap(long, default_value_t = 1.0)]
+    fence_height: f32,
+    /// What connects the fence posts
+    #[clap(arg_enum, long, default_value = "rails")]
+    fence_style: FenceStyle,
     #[clap(short, long)]
     file: bool,
+    /// Number of storeys in a house
+    #[clap(long, default_value_t = 1)]
+    floors: u32,
+    /// JSON file with the corners of a building footprint to put walls and
+    /// a hip roof on
+    #[clap(long)]
+    footprint: Option<std::path::PathBuf>,
+    /// How the surface noise octaves are combined
+    #[clap(arg_enum, long, default_value = "fbm")]
+    fractal: Fractal,
+    /// Width of the frames around house doors and windows
+    #[clap(long, default_value_t = 0.02)]
+    frame_width: f32,
+    /// Surface noise features per world unit
+    #[clap(long, default_value_t = 0.15)]
+    frequency: f32,
+    /// Amplitude multiplier between surface noise octaves
+    #[clap(long, default_value_t = 0.5)]
+    gain: f32,
+    /// Rewrite the rules of a plant this many times instead of the
+    /// preset's count
+    #[clap(long)]
+    generations: Option<u32>,
+    /// Replicate the last model added in a square grid this many copies wide
+    #[clap(long)]
+    grid: Option<u32>,
+    /// Height of each storey of a house
     #[clap(long, default_value_t = 1.0)]
     height: f32,
+    /// Add handrails to stairs
+    #[clap(long)]
+    handrails: bool,
+    /// Grayscale 8 or 16-bit image to load as terrain
+    #[clap(long)]
+    heightmap: Option<std::path::PathBuf>,
+    /// Height of white heightmap pixels
+    #[clap(long, default_value_t = 5.0)]
+    heightmap_max: f32,
+    /// Height of black heightmap pixels
+    #[clap(long, default_value_t = 0.0)]
+    heightmap_min: f32,
+    /// Distance between neighbouring heightmap pixels
+    #[clap(long, default_value_t = 0.1)]
+    heightmap_scale: f32,
     #[clap(short, long)]
     house: bool,
+    /// Print a validation report for every generated model and exit
+    /// without opening a window
+    #[clap(long)]
+    inspect: bool,
+    /// Largest random turn of each copy about its Y axis, in degrees
+    #[clap(long, default_value_t = 0.0)]
+    jitter_rotation: f32,
+    /// Largest random change in the size of each copy, as a fraction
+    #[clap(long, default_value_t = 0.0)]
+    jitter_scale: f32,
+    /// Frequency multiplier between surface noise octaves
+    #[clap(long, default_value_t = 2.0)]
+    lacunarity: f32,
     #[clap(long, default_value_t = 1.0)]
     length: f32,
     #[clap(long, default_value_t = 0.5)]
     max: f32,
+    /// Highest point of a surface that scattered copies are placed on
+    #[clap(long)]
+    max_height: Option<f32>,
+    /// Steepest surface that scattered copies are placed on, in degrees
+    #[clap(long, default_value_t = 90.0)]
+    max_slope: f32,
+    /// Lowest point of a surface that scattered copies are placed on
+    #[clap(long)]
+    min_height: Option<f32>,
+    /// Leave the stringers out from under stairs
+    #[clap(long)]
+    no_stringers: bool,
+    /// Kind of noise that raises the surface
+    #[clap(arg_enum, long, default_value = "perlin")]
+    noise: NoiseKind,
+    /// Number of surface noise octaves
+    #[clap(long, default_value_t = 4)]
+    octaves: u32,
+    /// How far a house roof reaches past the walls
+    #[clap(long, default_value_t = 0.2)]
+    overhang: f32,
+    /// Slope of a house roof, in degrees
+    #[clap(long, default_value_t = 30.0)]
+    pitch: f32,
     #[clap(short, long)]
     plane: bool,
+    /// Grow a plant from this L-system preset
+    #[clap(arg_enum, long)]
+    plant: Option<PlantPreset>,
+    /// Replicate the last model added this many times around the Y axis
+    #[clap(long)]
+    radial: Option<u32>,
+    /// Rails, or strands of wire, between fence posts
+    #[clap(long, default_value_t = 2)]
+    rails: u32,
+    /// Water each erosion droplet starts with
+    #[clap(long, default_value_t = 1.0)]
+    rain: f32,
+    /// Height of each step of a staircase
+    #[clap(long, default_value_t = 0.18)]
+    rise: f32,
+    /// Shape of a house roof
+    #[clap(arg_enum, long, default_value = "gable")]
+    roof: RoofStyle,
+    /// Depth of each step of a straight or L-shaped staircase
+    #[clap(long, default_value_t = 0.28)]
+    run: f32,
+    /// Scatter posts over the last model added, at least --spacing apart
+    #[clap(long)]
+    scatter: bool,
+    /// Seed for every procedural generator, so a run can be reproduced
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
     #[clap(long, default_value_t = 1.0)]
     size: f32,
+    /// Run fences along a curve through their points
+    #[clap(long)]
+    smooth: bool,
+    /// Distance between copies, the radius of radial copies, the least
+    /// distance between scattered copies or the most between fence posts
+    #[clap(long, default_value_t = 2.0)]
+    spacing: f32,
+    /// Add a staircase of this kind
+    #[clap(arg_enum, long)]
+    stairs: Option<StairKind>,
+    /// Number of steps in a staircase
+    #[clap(long, default_value_t = 12)]
+    steps: u32,
+    /// Stream chunks of noise terrain around the camera
+    #[clap(long)]
+    stream: bool,
     #[clap(short, long)]
     surface: bool,
+    /// Angle of repose for thermal erosion, in degrees
+    #[clap(long, default_value_t = 35.0)]
+    talus: f32,
+    /// Run this many passes of thermal erosion over the surface
+    #[clap(long)]
+    thermal: Option<u32>,
+    /// Turn between neighbouring steps of a spiral staircase, in degrees
+    #[clap(long, default_value_t = 22.5)]
+    turn: f32,
+    /// Distinct house models shared by the lots of a city
+    #[clap(long, default_value_t = 8)]
+    variants: u32,
+    /// Radius in chunks of the streamed terrain kept around the camera
+    #[clap(long, default_value_t = 8)]
+    view_distance: u32,
+    /// Rebuild every model but terrain from blocks this wide once they are
+    /// placed
+    #[clap(long)]
+    voxelize: Option<f32>,
+    /// Thickness of house walls
+    #[clap(long, default_value_t = 0.05)]
+    wall_thickness: f32,
+    /// How far the surface noise is domain warped, in world units
+    #[clap(long, default_value_t = 0.0)]
+    warp: f32,
     #[clap(long, default_value_t = 1.0)]
     width: f32,
+    /// Height of house windows
+    #[clap(long, default_value_t = 0.25)]
+    window_height: f32,
+    /// Height of the bottom of house windows above their floor
+    #[clap(long, default_value_t = 0.35)]
+    window_sill: f32,
+    /// Length of house wall given one window on each storey
+    #[clap(long, default_value_t = 0.5)]
+    window_spacing: f32,
+    /// Width of house windows, or 0 for no windows
+    #[clap(long, default_value_t = 0.18)]
+    window_width: f32,
 }
 
 fn main() {
     env_logger::init();
     let cli = Cli::parse();
+    let noise = NoiseSettings {
+        kind: cli.noise,
+        fractal: cli.fractal,
+        frequency: cli.frequency,
+        octaves: cli.octaves,
+        lacunarity: cli.lacunarity,
+        gain: cli.gain,
+        warp: cli.warp,
+        ..Default::default()
+    };
+    let house = HouseSettings {
+        width: cli.width,
+        length: cli.length,
+        floors: cli.floors,
+        floor_height: cli.height,
+        wall_thickness: cli.wall_thickness,
+        roof: cli.roof,
+        pitch: Deg(cli.pitch).into(),
+        overhang: cli.overhang,
+        door_width: cli.door_width,
+        door_height: cli.door_height,
+        window_width: cli.window_width,
+        window_height: cli.window_height,
+        window_sill: cli.window_sill,
+        window_spacing: cli.window_spacing,
+        frame_width: cli.frame_width,
+    };
+
+    if cli.inspect {
+        let valid = inspect(&cli, noise, &house);
+
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut state = pollster::block_on(State::new(&window));
+    // The seed is shown in the title so a run can be reproduced without
+    // exporting anything.
+    let window = WindowBuilder::new()
+        .with_title(format!("tarnish - seed {}", cli.seed))
+        .build(&event_loop)
+        .unwrap();
+    let mut state = pollster::block_on(State::new(&window, cli.seed));
 
     state.render().unwrap();
 
+    if let Some(settings) = city_settings(&cli) {
+        state.add_city(&settings);
+    }
     if cli.cube {
         state.add_model_primitive(ModelPrimitive::Cube, cli.size);
     }
     if cli.file {
         state.prompt_for_file().unwrap();
     }
+    if let Some(path) = &cli.footprint {
+        state.add_footprint_house(path, &house).unwrap();
+    }
+    if let Some(path) = &cli.heightmap {
+        state.add_heightmap(
+            path,
+            cli.heightmap_scale,
+            cli.heightmap_min,
+            cli.heightmap_max,
+            cli.downsample,
+        ).unwrap();
+    }
     if cli.house {
-        state.add_house(cli.width, cli.length, cli.height);
+        state.add_house(&house);
     }
     if cli.plane {
         state.add_model_primitive(ModelPrimitive::Plane, cli.size);
     }
+    if let Some(settings) = stair_settings(&cli) {
+        state.add_stairs(&settings);
+    }
+    if let Some(preset) = cli.plant {
+        state.add_plant(preset, cli.generations);
+    }
     if cli.surface {
-        state.add_surface(cli.count, cli.size, cli.max);
+        state.add_surface(cli.count, cli.size, cli.max, noise, erosion_settings(&cli));
+    }
+    if cli.stream {
+        state.stream_terrain(StreamSettings {
+            chunk_size: cli.chunk_size,
+            height_max: cli.max,
+            view_distance: cli.view_distance,
+            ..Default::default()
+        }, noise);
+    }
+    if let Some(path) = &cli.fence {
+        state.add_fence(path, &fence_settings(&cli)).unwrap();
+    }
+    if let Some(modifier) = modifier(&cli) {
+        let jitter = Jitter {
+            rotation: Deg(cli.jitter_rotation).into(),
+            scale: cli.jitter_scale,
+            ..Default::default()
+        };
+
+        state.apply_modifier(&modifier, &jitter);
+    }
+    if cli.scatter {
+        state.scatter_posts(&ScatterSettings {
+            density: cli.density,
+            spacing: cli.spacing,
+            max_slope: Deg(cli.max_slope).into(),
+            min_height: cli.min_height.unwrap_or(f32::NEG_INFINITY),
+            max_height: cli.max_height.unwrap_or(f32::INFINITY),
+            align_to_normal: cli.align,
+            ..Default::default()
+        });
+    }
+    if let Some(size) = cli.voxelize {
+        state.voxelize_models(size).unwrap();
+    }
+    if let Some(scheme) = cli.colors {
+        state.color_models(scheme);
+    }
+    if let Some(prefix) = &cli.export {
+        state.export_terrain(prefix).unwrap();
     }
 
     let mut last_render_time = std::time::Instant::now();
@@ -112,7 +471,9 @@ fn main() {
                     WindowEvent::ScaleFactorChanged { new_inner_size, ..} => {
                         state.resize(**new_inner_size);
                     }
-                    _ => {}
+                    _ => {
+                        state.window_input(event);
+                    }
                 }
             }
             Event::RedrawRequested(_) => {
@@ -136,4 +497,157 @@ fn main() {
     });
 }
 
+fn city_settings(cli: &Cli) -> Option<CitySettings> {
+    cli.city.map(|size| CitySettings {
+        width: size,
+        length: size,
+        block_size: cli.block_size,
+        variants: cli.variants,
+        seed: cli.seed,
+        ..Default::default()
+    })
+}
+
+fn erosion_settings(cli: &Cli) -> ErosionSettings {
+    ErosionSettings {
+        hydraulic: cli.erosion.map(|iterations| HydraulicSettings {
+            iterations,
+            rain: cli.rain,
+            evaporation: cli.evaporation,
+            sediment_capacity: cli.capacity,
+            seed: cli.seed,
+            ..Default::default()
+        }),
+        thermal: cli.thermal.map(|iterations| ThermalSettings {
+            iterations,
+            talus: Deg(cli.talus).into(),
+            ..Default::default()
+        }),
+    }
+}
+
+fn fence_settings(cli: &Cli) -> FenceSettings {
+    FenceSettings {
+        style: cli.fence_style,
+        spacing: cli.spacing,
+        height: cli.fence_height,
+        rails: cli.rails,
+        smooth: cli.smooth,
+        ..Default::default()
+    }
+}
+
+/// Validates the meshes of the models asked for on the command line
+/// without opening a window, and returns whether all of them passed.
+/// Loaded files and streamed terrain are only checked in the viewer.
+fn inspect(cli: &Cli, noise: NoiseSettings, house: &HouseSettings) -> bool {
+    let mut builders = Vec::new();
+    let mut surface = None;
+
+    if let Some(settings) = city_settings(cli) {
+        let city = city::generate(&settings);
+
+        builders.push(city.ground);
+        builders.extend(city.houses.iter().map(house::build));
+    }
+    if cli.cube {
+        builders.push(ModelPrimitive::Cube.build(cli.size));
+    }
+    if let Some(path) = &cli.footprint {
+        builders.push(house::build_footprint(&house::load_footprint(path).unwrap(), house));
+    }
+    if let Some(path) = &cli.heightmap {
+        surface = Some(HeightField::from_image(
+            path,
+            cli.heightmap_scale,
+            cli.heightmap_min,
+            cli.heightmap_max,
+            cli.downsample,
+        ).unwrap());
+        builders.push(terrain_builder("Heightmap", surface.as_ref().unwrap(), cli.heightmap_min, cli.heightmap_max));
+    }
+    if cli.house {
+        let mut post = MeshBuilder::new("Post");
+
+        Model::add_post(&mut post, Vector3::new(0.0, 0.0, 0.0), HOUSE_POST_SIZE, HOUSE_POST_SIZE, house.wall_height());
+        builders.push(house::build(house));
+        builders.push(post);
+    }
+    if cli.plane {
+        builders.push(ModelPrimitive::Plane.build(cli.size));
+    }
+    if let Some(settings) = stair_settings(cli) {
+        builders.push(stairs::build(&settings));
+    }
+    if let Some(preset) = cli.plant {
+        let mut system = LSystem::preset(preset);
 
+        if let Some(iterations) = cli.generations {
+            system.iterations = iterations;
+        }
+        builders.push(system.build(cli.seed));
+    }
+    if cli.surface {
+        let noise = Noise::new(NoiseSettings { seed: cli.seed, ..noise });
+        let mut field = HeightField::from_noise(&noise, cli.count, 2.0 * cli.size, cli.max);
+
+        erosion_settings(cli).apply(&mut field);
+        builders.push(terrain_builder("Surface", &field, 0.0, cli.max));
+        surface = Some(field);
+    }
+    if let Some(path) = &cli.fence {
+        let points = fence::load_points(path).unwrap();
+
+        let surface = surface.as_ref().map(|field| (field, Matrix4::identity()));
+
+        builders.push(fence::build(&points, &fence_settings(cli), surface));
+    }
+
+    println!("Seed: {}", cli.seed);
+
+    builders.iter().map(MeshBuilder::validate).fold(true, |valid, report| {
+        print!("{}", report);
+        valid && report.is_valid()
+    })
+}
+
+fn modifier(cli: &Cli) -> Option<InstanceModifier> {
+    if let Some(count) = cli.array {
+        Some(InstanceModifier::Array {
+            count,
+            offset: Vector3::new(cli.spacing, 0.0, 0.0),
+        })
+    } else if let Some(count) = cli.grid {
+        Some(InstanceModifier::Grid {
+            columns: count,
+            rows: count,
+            spacing: Vector2::new(cli.spacing, cli.spacing),
+        })
+    } else if let Some(count) = cli.radial {
+        Some(InstanceModifier::Radial {
+            count,
+            radius: cli.spacing,
+            axis: Vector3::unit_y(),
+        })
+    } else {
+        cli.curve.as_ref().map(|path| InstanceModifier::Curve {
+            points: fence::load_points(path).unwrap(),
+            count: cli.copies,
+            align: cli.align,
+        })
+    }
+}
+
+fn stair_settings(cli: &Cli) -> Option<StairSettings> {
+    cli.stairs.map(|kind| StairSettings {
+        kind,
+        steps: cli.steps,
+        rise: cli.rise,
+        run: cli.run,
+        width: cli.width,
+        stringers: !cli.no_stringers,
+        handrails: cli.handrails,
+        turn: Deg(cli.turn).into(),
+        ..Default::default()
+    })
+}
@@ -1,9 +1,13 @@
-use crate::texture::Texture;
+use wgpu::util::DeviceExt;
+
+use crate::color::ColorUniform;
+
+pub const DEFAULT_TINT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
 pub struct Material {
   pub name: String,
-  pub diffuse_texture: Texture,
-  pub normal_texture: Texture,
+  tint: ColorUniform,
+  buffer: wgpu::Buffer,
   pub bind_group: wgpu::BindGroup,
 }
 
@@ -11,28 +15,23 @@ impl Material {
   pub fn new(
     device: &wgpu::Device,
     name: &str,
-    diffuse_texture: Texture,
-    normal_texture: Texture,
+    tint: [f32; 4],
     layout: &wgpu::BindGroupLayout,
   ) -> Self {
+    let tint = ColorUniform { color: tint };
+    let buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Material Buffer", name)),
+        contents: bytemuck::cast_slice(&[tint]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      }
+    );
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
       layout,
       entries: &[
         wgpu::BindGroupEntry {
           binding: 0,
-          resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-        },
-        wgpu::BindGroupEntry {
-          binding: 2,
-          resource: wgpu::BindingResource::TextureView(&normal_texture.view),
-        },
-        wgpu::BindGroupEntry {
-          binding: 3,
-          resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+          resource: buffer.as_entire_binding(),
         },
       ],
       label: Some(name),
@@ -40,9 +39,38 @@ impl Material {
 
     Self {
       name: String::from(name),
-      diffuse_texture,
-      normal_texture,
+      tint,
+      buffer,
       bind_group,
     }
   }
+
+  /// Changes the color multiplied with the vertex colors of every mesh
+  /// drawn with the material.
+  pub fn set_tint(&mut self, queue: &wgpu::Queue, tint: [f32; 4]) {
+    self.tint.color = tint;
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.tint]));
+  }
+
+  pub fn tint(&self) -> [f32; 4] {
+    self.tint.color
+  }
+
+  pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }
+      ],
+      label: Some("material_bind_group_layout"),
+    })
+  }
 }
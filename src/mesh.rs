@@ -1,7 +1,19 @@
-use cgmath::{InnerSpace, Vector3};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
 use wgpu::util::DeviceExt;
 
-const COLOR: [f32;4] = [1.0, 0.1, 0.1, 1.0];
+use crate::{
+  bounds::{Aabb, BoundingSphere},
+  bvh::Bvh,
+  color::ColorGenerator,
+  ray::{Ray, TriangleHit},
+  validation::{validate, ValidationReport},
+};
+
+/// Vertex color of faces that take their color from their material.
+pub const COLOR: [f32;4] = [1.0, 1.0, 1.0, 1.0];
+/// Twice the area below which `add_polygon` drops a triangle, and at which
+/// validation reports one as degenerate.
+pub const DEGENERATE_AREA: f32 = 1e-10;
 
 pub trait Vertex {
   fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -9,36 +21,200 @@ pub trait Vertex {
 
 pub struct Mesh {
   pub name: String,
+  pub vertices: Vec<MeshVertex>,
+  pub indices: Vec<u32>,
   pub vertex_buffer: wgpu::Buffer,
   pub index_buffer: wgpu::Buffer,
   pub num_elements: u32,
   pub material: usize,
+  pub aabb: Aabb,
+  pub bounding_sphere: BoundingSphere,
+  pub bvh: Bvh,
+}
+
+/// Geometry of a mesh and its BVH, ready to upload. Built apart from the
+/// buffers so the slow part can run off the main thread.
+pub struct MeshData {
+  pub name: String,
+  pub vertices: Vec<MeshVertex>,
+  pub indices: Vec<u32>,
+  pub material: usize,
+  pub bvh: Bvh,
+}
+
+impl MeshData {
+  pub fn new(name: &str, vertices: Vec<MeshVertex>, indices: Vec<u32>, material: usize) -> Self {
+    let bvh = Bvh::new(&vertices, &indices);
+
+    Self {
+      name: String::from(name),
+      vertices,
+      indices,
+      material,
+      bvh,
+    }
+  }
+}
+
+impl Mesh {
+  pub fn new(
+    device: &wgpu::Device,
+    name: &str,
+    vertices: &[MeshVertex],
+    indices: &[u32],
+    material: usize,
+  ) -> Self {
+    Self::upload(device, MeshData::new(name, vertices.to_vec(), indices.to_vec(), material))
+  }
+
+  /// Creates the buffers of prepared geometry, keeping its BVH.
+  pub fn upload(device: &wgpu::Device, data: MeshData) -> Self {
+    let MeshData { name, vertices, indices, material, bvh } = data;
+    let vertex_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Vertex Buffer", name)),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+      }
+    );
+    let index_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Index Buffer", name)),
+        contents: bytemuck::cast_slice(&indices),
+        usage: wgpu::BufferUsages::INDEX,
+      }
+    );
+
+    let points = vertices.iter().map(|vertex| Point3::from(vertex.position));
+    let aabb = Aabb::from_points(points.clone());
+    let bounding_sphere = BoundingSphere::from_points(points);
+    let num_elements = indices.len() as u32;
+
+    Self {
+      name,
+      vertices,
+      indices,
+      vertex_buffer,
+      index_buffer,
+      num_elements,
+      material,
+      aabb,
+      bounding_sphere,
+      bvh,
+    }
+  }
+
+  /// Recolors the vertices and uploads them again.
+  pub fn apply_colors(&mut self, queue: &wgpu::Queue, generator: &ColorGenerator) {
+    apply_colors(&mut self.vertices, &self.indices, generator);
+    queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+  }
+
+  pub fn raycast(&self, ray: &Ray) -> Option<TriangleHit> {
+    self.bvh.intersect(ray, &self.vertices, &self.indices)
+  }
+
+  pub fn validate(&self) -> ValidationReport {
+    validate(&self.name, &self.vertices, &self.indices)
+  }
 }
 
 pub struct MeshBuilder {
+  color: [f32;4],
+  face_materials: Vec<usize>,
   indices: Vec<u32>,
   label: String,
+  material: usize,
   vertices: Vec<MeshVertex>,
 }
 
 impl MeshBuilder {
   pub fn new(label: &str) -> Self {
     Self {
+      color: COLOR,
+      face_materials: Vec::new(),
       indices: Vec::new(),
       label: String::from(label),
+      material: 0,
       vertices: Vec::new(),
     }
   }
 
+  /// Adds the six outward facing sides of an axis aligned box.
+  pub fn add_box(&mut self, near_corner: Vector3<f32>, size: Vector3<f32>) {
+    let up = size.y * Vector3::unit_y();
+    let right = size.x * Vector3::unit_x();
+    let forward = size.z * Vector3::unit_z();
+    let far_corner = near_corner + size;
+
+    self.add_quad(near_corner, forward, right);
+    self.add_quad(near_corner, right, up);
+    self.add_quad(near_corner, up, forward);
+
+    self.add_quad(far_corner, -right, -forward);
+    self.add_quad(far_corner, -up, -right);
+    self.add_quad(far_corner, -forward, -up);
+  }
+
+  /// Adds a beam, a box from `start` to `end` whose cross-section is
+  /// `size.x` wide along `across` and `size.y` wide at right angles to it.
+  /// `across` must not be parallel to the beam.
+  pub fn add_beam(&mut self, start: Vector3<f32>, end: Vector3<f32>, across: Vector3<f32>, size: Vector2<f32>) {
+    let along = end - start;
+    let side = across.normalize() * size.x / 2.0;
+    let other = along.cross(across).normalize() * size.y / 2.0;
+    let corners = [side + other, side - other, -side - other, -side + other];
+
+    for i in 0..4 {
+      let (a, b) = (corners[i], corners[(i + 1) % 4]);
+
+      self.add_polygon(&[start + a, end + a, end + b, start + b], a + b);
+    }
+
+    self.add_polygon(&corners.map(|corner| start + corner), -along);
+    self.add_polygon(&corners.map(|corner| end + corner), along);
+  }
+
+  pub fn add_colored_quad(
+    &mut self,
+    position: Vector3<f32>,
+    width: Vector3<f32>,
+    length: Vector3<f32>,
+    color: [f32;4],
+  ) {
+    let previous = self.color;
+
+    self.set_color(color);
+    self.add_quad(position, width, length);
+    self.set_color(previous);
+  }
+
+  pub fn add_colored_triangle(
+    &mut self,
+    v0: Vector3<f32>,
+    v1: Vector3<f32>,
+    v2: Vector3<f32>,
+    color: [f32;4],
+  ) {
+    let previous = self.color;
+
+    self.set_color(color);
+    self.add_triangle(v0, v1, v2);
+    self.set_color(previous);
+  }
+
   pub fn add_face(&mut self, indices: (u32, u32, u32)) {
     let (i1, i2, i3) = indices;
     self.indices.push(i1);
     self.indices.push(i2);
     self.indices.push(i3);
+    self.face_materials.push(self.material);
   }
 
-  pub fn add_linked_quad(&mut self, position: Vector3<f32>, link: bool, index_offset: u32) {
-    self.add_vertex(position, Vector3::unit_y());
+  /// Adds a grid vertex and, with `link`, the quad joining it to the
+  /// vertices before it in its row and in the row `index_offset` back.
+  pub fn add_linked_quad(&mut self, position: Vector3<f32>, normal: Vector3<f32>, link: bool, index_offset: u32) {
+    self.add_vertex(position, normal);
 
     if link {
       let i0 = self.vertices.len() as u32 - 1;
@@ -51,6 +227,27 @@ impl MeshBuilder {
     }
   }
 
+  /// Adds a convex polygon as a triangle fan, wound so its front faces
+  /// `facing`. Collinear points produce no triangles.
+  pub fn add_polygon(&mut self, points: &[Vector3<f32>], facing: Vector3<f32>) {
+    if points.len() < 3 {
+      return;
+    }
+
+    let normal = points.windows(2).skip(1)
+      .map(|edge| (edge[0] - points[0]).cross(edge[1] - points[0]))
+      .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+    let flip = normal.dot(facing) < 0.0;
+
+    for i in 1..points.len() - 1 {
+      let (v1, v2) = if flip { (points[i + 1], points[i]) } else { (points[i], points[i + 1]) };
+
+      if (v1 - points[0]).cross(v2 - points[0]).magnitude() > DEGENERATE_AREA {
+        self.add_triangle(points[0], v1, v2);
+      }
+    }
+  }
+
   pub fn add_quad(&mut self, position: Vector3<f32>, width: Vector3<f32>, length: Vector3<f32>) {
     let normal = length.cross(width).normalize();
 
@@ -85,33 +282,73 @@ impl MeshBuilder {
     self.vertices.push(MeshVertex {
       position: position.into(),
       normal: normal.into(),
-      color: COLOR,
+      color: self.color,
     });
   }
 
-  pub fn build(&self, device: &wgpu::Device) -> Mesh {
-    let vertex_buffer = device.create_buffer_init(
-      &wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{} Vertex Buffer", self.label)),
-        contents: bytemuck::cast_slice(&self.vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-      }
-    );
-    let index_buffer = device.create_buffer_init(
-      &wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{} Index Buffer", self.label)),
-        contents: bytemuck::cast_slice(&self.indices),
-        usage: wgpu::BufferUsages::INDEX,
-      }
-    );
+  /// Recolors every vertex already added to the builder. See
+  /// `apply_colors`.
+  pub fn apply_colors(&mut self, generator: &ColorGenerator) {
+    apply_colors(&mut self.vertices, &self.indices, generator);
+  }
 
-    Mesh {
-      name: String::from(&self.label),
-      vertex_buffer,
-      index_buffer,
-      num_elements: self.indices.len() as u32,
-      material: 0,
+  /// Geometry and BVH of one mesh per material slot used by the faces of
+  /// the builder, each holding only the vertices its faces reference.
+  /// Nothing touches the GPU, so this can run on a worker thread.
+  pub fn build(&self) -> Vec<MeshData> {
+    let mut slots = self.face_materials.clone();
+
+    slots.sort_unstable();
+    slots.dedup();
+
+    if slots.len() == 1 {
+      return vec![MeshData::new(&self.label, self.vertices.clone(), self.indices.clone(), slots[0])];
     }
+
+    slots.into_iter().map(|slot| {
+      let (vertices, indices) = self.material_geometry(slot);
+
+      MeshData::new(&format!("{} {}", self.label, slot), vertices, indices, slot)
+    }).collect()
+  }
+
+  fn material_geometry(&self, slot: usize) -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut remap = vec![u32::MAX; self.vertices.len()];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let faces = self.indices.chunks(3)
+      .zip(self.face_materials.iter())
+      .filter(|(_, &material)| material == slot);
+
+    for (face, _) in faces {
+      for &index in face {
+        let index = index as usize;
+
+        if remap[index] == u32::MAX {
+          remap[index] = vertices.len() as u32;
+          vertices.push(self.vertices[index]);
+        }
+
+        indices.push(remap[index]);
+      }
+    }
+
+    (vertices, indices)
+  }
+
+  /// Sets the vertex color of everything added from now on. Material tints
+  /// multiply it.
+  pub fn set_color(&mut self, color: [f32;4]) {
+    self.color = color;
+  }
+
+  /// Sets the material slot assigned to faces added from now on.
+  pub fn set_material(&mut self, material: usize) {
+    self.material = material;
+  }
+
+  pub fn validate(&self) -> ValidationReport {
+    validate(&self.label, &self.vertices, &self.indices)
   }
 }
 
@@ -150,3 +387,27 @@ impl Vertex for MeshVertex {
     }
   }
 }
+
+/// Recolors the vertices with the generator. Face based generators write
+/// to all three vertices of a face, so meshes with shared vertices take the
+/// color of the last face touching them.
+fn apply_colors(vertices: &mut [MeshVertex], indices: &[u32], generator: &ColorGenerator) {
+  match generator.face_colors() {
+    Some(mut face_colors) => {
+      for face in indices.chunks(3) {
+        let color = face_colors.next_color();
+
+        for &index in face {
+          vertices[index as usize].color = color;
+        }
+      }
+    }
+    None => {
+      for vertex in vertices.iter_mut() {
+        if let Some(color) = generator.vertex_color(vertex.position.into(), vertex.normal.into()) {
+          vertex.color = color;
+        }
+      }
+    }
+  }
+}
@@ -1,25 +1,103 @@
 use anyhow::Result;
-use cgmath::{InnerSpace, Vector3};
-use rand::Rng;
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::path::Path;
 use tobj::LoadOptions;
 use wgpu::util::DeviceExt;
 
-use crate::mesh::{Mesh, MeshBuilder, MeshVertex};
+use crate::{
+  bounds::{Aabb, BoundingSphere},
+  color::ColorGenerator,
+  erosion::ErosionSettings,
+  fence::{self, FenceSettings, FENCE_MATERIALS},
+  house::{self, HouseSettings, HOUSE_MATERIALS},
+  instance::{Instance, InstanceRaw},
+  lsystem::{LSystem, PLANT_MATERIALS},
+  material::{Material, DEFAULT_TINT},
+  mesh::{Mesh, MeshBuilder, MeshData, MeshVertex, COLOR},
+  noise::Noise,
+  ray::{Ray, TriangleHit},
+  stairs::{self, StairSettings, STAIR_MATERIALS},
+  terrain::HeightField,
+  transform::Transform,
+  validation::ValidationReport,
+  voxel::{self, VoxelVolume, VOXEL_MATERIALS},
+};
 
 const MODEL_COLOR: [f32;4] = [1.0, 0.1, 0.1, 1.0];
+const POST_COLOR: [f32;4] = [0.35, 0.25, 0.15, 1.0];
+const SURFACE_HIGH_COLOR: [f32;4] = [0.55, 0.5, 0.4, 1.0];
+const SURFACE_LOW_COLOR: [f32;4] = [0.2, 0.45, 0.15, 1.0];
 
 pub enum ModelPrimitive {
   Cube,
   Plane,
 }
 
+impl ModelPrimitive {
+  /// Mesh of the primitive `size` wide and centered on the origin.
+  pub fn build(&self, size: f32) -> MeshBuilder {
+    match self {
+      Self::Cube => {
+        let mut builder = MeshBuilder::new("Cube");
+        let near_corner = Vector3::new(-size / 2.0, -size / 2.0, -size / 2.0);
+
+        builder.add_box(near_corner, Vector3::new(size, size, size));
+        builder
+      }
+      Self::Plane => {
+        let mut builder = MeshBuilder::new("Plane");
+
+        builder.add_quad(
+          Vector3::new(-size / 2.0, 0.0, -size / 2.0),
+          Vector3::new(size, 0.0, 0.0),
+          Vector3::new(0.0, 0.0, size),
+        );
+        builder
+      }
+    }
+  }
+}
+
 pub struct Model {
+  pub aabb: Aabb,
+  pub bounding_sphere: BoundingSphere,
+  /// Heights the model was built from, if it is a terrain.
+  pub height_field: Option<HeightField>,
+  instance_buffer: wgpu::Buffer,
+  instance_capacity: usize,
+  instances: Vec<Instance>,
+  /// Inverse world matrix of each instance, or `None` where it cannot be
+  /// inverted, kept for ray casts.
+  inverse_matrices: Vec<Option<Matrix4<f32>>>,
+  pub materials: Vec<Material>,
   pub meshes: Vec<Mesh>,
+  pub transform: Transform,
 }
 
 impl Model {
+  pub fn new(device: &wgpu::Device, materials: Vec<Material>, meshes: Vec<Mesh>) -> Self {
+    let aabb = meshes.iter().fold(Aabb::empty(), |aabb, mesh| aabb.union(mesh.aabb));
+    let bounding_sphere = BoundingSphere::enclosing(meshes.iter().map(|mesh| mesh.bounding_sphere));
+    let transform = Transform::default();
+    let instances = vec![Instance::default()];
+    let instance_buffer = create_instance_buffer(device, &instance_data(&transform, &instances));
+    let inverse_matrices = inverse_matrices(&transform, &instances);
+
+    Self {
+      aabb,
+      bounding_sphere,
+      height_field: None,
+      instance_buffer,
+      instance_capacity: instances.len(),
+      instances,
+      inverse_matrices,
+      materials,
+      meshes,
+      transform,
+    }
+  }
+
   pub fn add_post(builder: &mut MeshBuilder, position: Vector3<f32>, width: f32, length: f32, height: f32) {
     let up = Vector3::unit_y() * height;
     let right = Vector3::unit_x() * width;
@@ -36,81 +114,90 @@ impl Model {
     builder.add_quad(far_corner, -forward, -up);
   }
 
-  pub fn cube(device: &wgpu::Device, size: f32) -> Self {
-    let mut builder = MeshBuilder::new("Cube");
-    let up = size * Vector3::unit_y();
-    let right = size * Vector3::unit_x();
-    let forward = size * Vector3::unit_z();
-    let near_corner = Vector3::new(-size / 2.0, -size / 2.0, -size / 2.0);
-    let far_corner = Vector3::new(size / 2.0, size / 2.0, size / 2.0);
-
-    builder.add_quad(near_corner, forward, right);
-    builder.add_quad(near_corner, right, up);
-    builder.add_quad(near_corner, up, forward);
-
-    builder.add_quad(far_corner, -right, -forward);
-    builder.add_quad(far_corner, -up, -right);
-    builder.add_quad(far_corner, -forward, -up);
-
-    let mesh = builder.build(device);
-
-    Self { meshes: vec![mesh] }
+  /// Model of the builder's meshes with a material for each `(name, tint)`
+  /// slot. Slots the builder uses beyond those get a plain white material.
+  pub fn from_builder(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    builder: &MeshBuilder,
+    materials: &[(&str, [f32;4])],
+  ) -> Self {
+    Self::from_data(device, layout, builder.build(), materials)
   }
 
-  pub fn house(device: &wgpu::Device, width: f32, length: f32, height: f32) -> Self {
-    let mut builder = MeshBuilder::new("House");
-
-    let up = height * Vector3::unit_y();
-    let right = width * Vector3::unit_x();
-    let mut forward = length * Vector3::unit_z();
-    let near_corner = Vector3::new(-width / 2.0, 0.0, -length / 2.0);
-    let far_corner = Vector3::new(width / 2.0, height, length / 2.0);
-
-    builder.add_quad(near_corner, right, up);
-    builder.add_quad(near_corner, up, forward);
-
-    builder.add_quad(far_corner, -up, -right);
-    builder.add_quad(far_corner, -forward, -up);
-
-    let wall_top_left = near_corner + up;
-    let wall_top_right = wall_top_left + right;
-    let mut roof_peak = wall_top_left + 0.5 * up + (width / 2.0) * right;
-
-    builder.add_triangle(wall_top_left, roof_peak, wall_top_right);
-    builder.add_triangle(wall_top_left + forward, wall_top_right + forward, roof_peak + forward);
-
-    let mut from_peak_left = wall_top_left - roof_peak;
-    let mut from_peak_right = wall_top_right - roof_peak;
-    let roof_overhang = 0.2;
-
-    from_peak_left += from_peak_left.normalize() * roof_overhang;
-    from_peak_right += from_peak_right.normalize() * roof_overhang;
+  /// Model from meshes prepared with `MeshBuilder::build`, with the
+  /// same material slots as `from_builder`.
+  pub fn from_data(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    meshes: Vec<MeshData>,
+    materials: &[(&str, [f32;4])],
+  ) -> Self {
+    let meshes = meshes.into_iter().map(|data| Mesh::upload(device, data)).collect::<Vec<_>>();
+    let mut materials = materials.iter().map(|(name, tint)| {
+      Material::new(device, name, *tint, layout)
+    }).collect::<Vec<_>>();
+
+    add_default_materials(device, layout, &mut materials, &meshes);
+
+    Self::new(device, materials, meshes)
+  }
 
-    roof_peak -= Vector3::unit_z() * roof_overhang;
+  /// Fence along the points, standing on `surface` placed by its world
+  /// matrix when given. See `fence::build`.
+  pub fn fence(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    points: &[Vector3<f32>],
+    settings: &FenceSettings,
+    surface: Option<(&HeightField, Matrix4<f32>)>,
+  ) -> Self {
+    Self::from_builder(device, layout, &fence::build(points, settings, surface), &FENCE_MATERIALS)
+  }
 
-    // Bias roof upwards for z-fighting
-    roof_peak += Vector3::unit_y() * 0.001;
-    forward += Vector3::unit_z() * roof_overhang * 2.0;
+  /// Building with walls on every side of a footprint and a hip roof over
+  /// it. See `house::build_footprint`.
+  pub fn footprint_house(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    footprint: &[Vector2<f32>],
+    settings: &HouseSettings,
+  ) -> Self {
+    Self::from_builder(device, layout, &house::build_footprint(footprint, settings), &HOUSE_MATERIALS)
+  }
 
-    builder.add_quad(roof_peak, forward, from_peak_left);
-    builder.add_quad(roof_peak, from_peak_left, forward);
-    builder.add_quad(roof_peak, from_peak_right, forward);
-    builder.add_quad(roof_peak, forward, from_peak_right);
+  /// Terrain from a grayscale image. See `HeightField::from_image`.
+  pub fn heightmap<P: AsRef<Path>>(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    path: P,
+    scale: f32,
+    height_min: f32,
+    height_max: f32,
+    step: u32,
+  ) -> Result<Self> {
+    let field = HeightField::from_image(path, scale, height_min, height_max, step)?;
 
-    let mesh = builder.build(device);
+    Ok(Self::terrain(device, layout, "Heightmap", field, height_min, height_max))
+  }
 
-    Self { meshes: vec![mesh] }
+  pub fn house(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, settings: &HouseSettings) -> Self {
+    Self::from_builder(device, layout, &house::build(settings), &HOUSE_MATERIALS)
   }
 
   pub fn load<P: AsRef<Path>>(
     device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
     path: P,
   ) -> Result<Self> {
-    let (obj_models, _) = tobj::load_obj(path.as_ref(), &LoadOptions {
+    let (obj_models, obj_materials) = tobj::load_obj(path.as_ref(), &LoadOptions {
       triangulate: true,
       single_index: true,
       ..Default::default()
     })?;
+    let obj_materials = obj_materials.unwrap_or_default();
+    // Meshes without a material of their own share one after the file's.
+    let default_material = obj_materials.len();
     let meshes = obj_models.iter().map(|m| {
       let vertices = (0..m.mesh.positions.len() / 3).into_par_iter().map(|i| {
         MeshVertex {
@@ -124,71 +211,273 @@ impl Model {
             m.mesh.normals[i * 3 + 1],
             m.mesh.normals[i * 3 + 2],
           ].into(),
-          color: MODEL_COLOR,
+          color: COLOR,
         }
       }).collect::<Vec<_>>();
 
-      let vertex_buffer = device.create_buffer_init(
-        &wgpu::util::BufferInitDescriptor {
-          label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
-          contents: bytemuck::cast_slice(&vertices),
-          usage: wgpu::BufferUsages::VERTEX,
-        }
-      );
-      let index_buffer = device.create_buffer_init(
-        &wgpu::util::BufferInitDescriptor {
-          label: Some(&format!("{:?} Index Buffer", path.as_ref())),
-          contents: bytemuck::cast_slice(&m.mesh.indices),
-          usage: wgpu::BufferUsages::INDEX,
-        }
-      );
-
-      Ok(Mesh {
-        name: String::from(&m.name),
-        vertex_buffer,
-        index_buffer,
-        num_elements: m.mesh.indices.len() as u32,
-        material: m.mesh.material_id.unwrap_or(0),
-      })
+      Ok(Mesh::new(
+        device,
+        &m.name,
+        &vertices,
+        &m.mesh.indices,
+        m.mesh.material_id.filter(|&id| id < obj_materials.len()).unwrap_or(default_material),
+      ))
     }).collect::<Result<Vec<_>>>()?;
+    let mut materials = obj_materials.iter().map(|material| {
+      let [r, g, b] = material.diffuse;
+
+      Material::new(device, &material.name, [r, g, b, material.dissolve], layout)
+    }).collect::<Vec<_>>();
+
+    if meshes.iter().any(|mesh| mesh.material == default_material) {
+      materials.push(Material::new(device, "Default", MODEL_COLOR, layout));
+    }
 
-    Ok(Self { meshes })
+    Ok(Self::new(device, materials, meshes))
   }
 
-  pub fn plane(device: &wgpu::Device, size: f32) -> Self {
-    let mut builder = MeshBuilder::new("Plane");
+  /// Plant drawn by an L-system. See `LSystem::build`.
+  pub fn plant(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, system: &LSystem, seed: u64) -> Self {
+    Self::from_builder(device, layout, &system.build(seed), &PLANT_MATERIALS)
+  }
 
-    builder.add_quad(
-      Vector3::new(-size / 2.0, 0.0, -size / 2.0),
-      Vector3::new(size, 0.0, 0.0),
-      Vector3::new(0.0, 0.0, size),
-    );
+  pub fn primitive(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    primitive: ModelPrimitive,
+    size: f32,
+  ) -> Self {
+    let name = match primitive {
+      ModelPrimitive::Cube => "Cube",
+      ModelPrimitive::Plane => "Plane",
+    };
+
+    Self::from_builder(device, layout, &primitive.build(size), &[(name, MODEL_COLOR)])
+  }
+
+  pub fn post(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    width: f32,
+    length: f32,
+    height: f32,
+  ) -> Self {
+    let mut builder = MeshBuilder::new("Post");
 
-    let mesh = builder.build(device);
+    Self::add_post(&mut builder, Vector3::new(0.0, 0.0, 0.0), width, length, height);
 
-    Self { meshes: vec![mesh] }
+    Self::from_builder(device, layout, &builder, &[("Post", POST_COLOR)])
   }
 
-  pub fn surface(device: &wgpu::Device, count: u32, size: f32, height_max: f32) -> Self {
-    let mut builder = MeshBuilder::new("Quad Grid");
-    let half_count = count as i32 / 2;
-    let mut rng = rand::thread_rng();
+  pub fn stairs(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, settings: &StairSettings) -> Self {
+    Self::from_builder(device, layout, &stairs::build(settings), &STAIR_MATERIALS)
+  }
 
-    for i in -half_count..half_count + 1 {
-      let z = 2.0 * size * i as f32;
+  /// Grid of `count` by `count` quads `2 * size` wide, raised by the noise
+  /// up to `height_max` and then eroded.
+  pub fn surface(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    count: u32,
+    size: f32,
+    height_max: f32,
+    noise: &Noise,
+    erosion: &ErosionSettings,
+  ) -> Self {
+    let mut field = HeightField::from_noise(noise, count, 2.0 * size, height_max);
+
+    erosion.apply(&mut field);
+
+    Self::terrain(device, layout, "Surface", field, 0.0, height_max)
+  }
 
-      for j in -half_count..half_count + 1 {
-        let x = 2.0 * size * j as f32;
-        let y = rng.gen_range(0.0..height_max);
-        let position = Vector3::new(x, y, z);
-        let link = i > -half_count && j > -half_count;
+  /// Mesh of the height field, colored from low to high between
+  /// `height_min` and `height_max`. The model keeps the field for export.
+  pub fn terrain(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    name: &str,
+    field: HeightField,
+    height_min: f32,
+    height_max: f32,
+  ) -> Self {
+    let meshes = terrain_builder(name, &field, height_min, height_max).build();
+
+    Self::terrain_from_data(device, layout, name, field, meshes)
+  }
 
-        builder.add_linked_quad(position, link, count + 1);
-      }
+  /// Terrain model from meshes already prepared from `field` with
+  /// `terrain_builder`, so streamed chunks only upload on the main thread.
+  pub fn terrain_from_data(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    name: &str,
+    field: HeightField,
+    meshes: Vec<MeshData>,
+  ) -> Self {
+    let mut model = Self::from_data(device, layout, meshes, &[(name, DEFAULT_TINT)]);
+
+    model.height_field = Some(field);
+    model
+  }
+
+  /// Greedy mesh of a voxel volume. See `voxel::build`.
+  pub fn voxels(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, volume: &impl VoxelVolume, voxel_size: f32) -> Self {
+    Self::from_builder(device, layout, &voxel::build(volume, voxel_size), &VOXEL_MATERIALS)
+  }
+
+  /// Recolors the vertices of every mesh with the generator. Material tints
+  /// are reset to white so the generated colors show unchanged.
+  pub fn apply_colors(&mut self, queue: &wgpu::Queue, generator: &ColorGenerator) {
+    for mesh in self.meshes.iter_mut() {
+      mesh.apply_colors(queue, generator);
     }
 
-    let mesh = builder.build(device);
+    for material in self.materials.iter_mut() {
+      material.set_tint(queue, DEFAULT_TINT);
+    }
+  }
+
+  pub fn instance_buffer(&self) -> &wgpu::Buffer {
+    &self.instance_buffer
+  }
+
+  /// World matrix of one copy of the model.
+  pub fn instance_matrix(&self, index: usize) -> Matrix4<f32> {
+    self.transform.matrix() * self.instances[index].matrix()
+  }
+
+  pub fn instances(&self) -> &[Instance] {
+    &self.instances
+  }
+
+  /// Inverse of `instance_matrix`, or `None` if the copy is scaled flat.
+  pub fn inverse_instance_matrix(&self, index: usize) -> Option<Matrix4<f32>> {
+    self.inverse_matrices[index]
+  }
+
+  /// Sphere around every instance in the space of the model's transform.
+  pub fn local_bounding_sphere(&self) -> BoundingSphere {
+    BoundingSphere::enclosing(self.instances.iter().map(|instance| {
+      self.bounding_sphere.transform(instance.matrix())
+    }))
+  }
+
+  pub fn num_instances(&self) -> u32 {
+    self.instances.len() as u32
+  }
 
-    Self { meshes: vec![mesh] }
+  /// Closest hit against the meshes of the model, with the index of the
+  /// mesh that was hit.
+  pub fn raycast(&self, ray: &Ray) -> Option<(usize, TriangleHit)> {
+    self.meshes.iter()
+      .enumerate()
+      .filter_map(|(i, mesh)| mesh.raycast(ray).map(|hit| (i, hit)))
+      .min_by(|(_, a), (_, b)| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal))
+  }
+
+  /// Replaces the copies of the model, growing the GPU buffer when the new
+  /// list does not fit in the current one.
+  pub fn set_instances(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: Vec<Instance>) {
+    self.instances = instances;
+    self.inverse_matrices = inverse_matrices(&self.transform, &self.instances);
+
+    let data = instance_data(&self.transform, &self.instances);
+
+    if self.instances.len() > self.instance_capacity {
+      self.instance_capacity = self.instances.len().next_power_of_two();
+
+      let mut padded = data;
+
+      padded.resize(self.instance_capacity, InstanceRaw::from_matrix(Matrix4::identity()));
+      self.instance_buffer = create_instance_buffer(device, &padded);
+    } else {
+      queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&data));
+    }
   }
+
+  pub fn set_transform(&mut self, queue: &wgpu::Queue, transform: Transform) {
+    self.transform = transform;
+    self.inverse_matrices = inverse_matrices(&self.transform, &self.instances);
+    queue.write_buffer(
+      &self.instance_buffer,
+      0,
+      bytemuck::cast_slice(&instance_data(&self.transform, &self.instances)),
+    );
+  }
+
+  /// Validation report of every mesh, named after the mesh and its
+  /// material.
+  pub fn validate(&self) -> Vec<ValidationReport> {
+    self.meshes.iter().map(|mesh| {
+      let mut report = mesh.validate();
+
+      if let Some(material) = self.materials.get(mesh.material) {
+        report.name = format!("{} ({})", report.name, material.name);
+      }
+
+      report
+    }).collect()
+  }
+
+  pub fn world_bounding_sphere(&self) -> BoundingSphere {
+    self.local_bounding_sphere().transform(self.transform.matrix())
+  }
+}
+
+/// Pads `materials` with white ones until every mesh's slot has a material.
+fn add_default_materials(
+  device: &wgpu::Device,
+  layout: &wgpu::BindGroupLayout,
+  materials: &mut Vec<Material>,
+  meshes: &[Mesh],
+) {
+  let slots = meshes.iter().map(|mesh| mesh.material + 1).max().unwrap_or(0);
+
+  while materials.len() < slots {
+    materials.push(Material::new(device, "Default", DEFAULT_TINT, layout));
+  }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, data: &[InstanceRaw]) -> wgpu::Buffer {
+  device.create_buffer_init(
+    &wgpu::util::BufferInitDescriptor {
+      label: Some("Instance Buffer"),
+      contents: bytemuck::cast_slice(data),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    }
+  )
+}
+
+fn inverse_matrices(transform: &Transform, instances: &[Instance]) -> Vec<Option<Matrix4<f32>>> {
+  let parent = transform.matrix();
+
+  instances.iter().map(|instance| (parent * instance.matrix()).invert()).collect()
+}
+
+fn instance_data(transform: &Transform, instances: &[Instance]) -> Vec<InstanceRaw> {
+  let parent = transform.matrix();
+
+  instances.iter().map(|instance| instance.to_raw(parent)).collect()
+}
+
+/// Builder of the height field's mesh, colored from low to high between
+/// `height_min` and `height_max`.
+pub fn terrain_builder(name: &str, field: &HeightField, height_min: f32, height_max: f32) -> MeshBuilder {
+  let mut builder = MeshBuilder::new(name);
+
+  field.add_to(&mut builder);
+  color_terrain(&mut builder, height_min, height_max);
+  builder
+}
+
+/// Colors terrain added to the builder from low to high between
+/// `height_min` and `height_max`.
+pub fn color_terrain(builder: &mut MeshBuilder, height_min: f32, height_max: f32) {
+  builder.apply_colors(&ColorGenerator::Height {
+    low: SURFACE_LOW_COLOR,
+    high: SURFACE_HIGH_COLOR,
+    min: height_min,
+    max: height_max,
+  });
 }
@@ -0,0 +1,223 @@
+use cgmath::{InnerSpace, One, Quaternion, Rad, Rotation, Rotation3, Vector2, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::PI;
+
+use crate::instance::Instance;
+
+/// Points sampled on each span of a Catmull-Rom curve.
+pub const CURVE_SAMPLES: u32 = 16;
+
+/// Ways of replicating a model as a list of instances instead of copying
+/// its geometry.
+pub enum InstanceModifier {
+  /// `count` copies in a line, each `offset` from the one before it.
+  Array {
+    count: u32,
+    offset: Vector3<f32>,
+  },
+  /// `columns` by `rows` copies on the XZ plane, centered on the origin.
+  Grid {
+    columns: u32,
+    rows: u32,
+    spacing: Vector2<f32>,
+  },
+  /// `count` copies spaced evenly around `axis` at `radius`, each turned
+  /// with its angle so they all face outward.
+  Radial {
+    count: u32,
+    radius: f32,
+    axis: Vector3<f32>,
+  },
+  /// `count` copies spaced evenly along a Catmull-Rom curve through
+  /// `points`. With `align` each copy is turned so its +Z follows the curve.
+  Curve {
+    points: Vec<Vector3<f32>>,
+    count: u32,
+    align: bool,
+  },
+}
+
+/// Random variation applied to every copy. Rotation turns a copy about its
+/// own Y axis by up to `rotation` either way, and scale multiplies it
+/// uniformly by up to `scale` more or less than one. The same seed always
+/// gives the same copies.
+#[derive(Copy, Clone, Debug)]
+pub struct Jitter {
+  pub rotation: Rad<f32>,
+  pub scale: f32,
+  pub seed: u64,
+}
+
+impl Default for Jitter {
+  fn default() -> Self {
+    Self {
+      rotation: Rad(0.0),
+      scale: 0.0,
+      seed: 0,
+    }
+  }
+}
+
+impl InstanceModifier {
+  pub fn instances(&self, jitter: &Jitter) -> Vec<Instance> {
+    let mut instances: Vec<Instance> = match self {
+      InstanceModifier::Array { count, offset } => (0..*count)
+        .map(|i| instance_at(offset * i as f32, Quaternion::one()))
+        .collect(),
+      InstanceModifier::Grid { columns, rows, spacing } => {
+        let origin = Vector2::new(
+          (*columns as f32 - 1.0) * spacing.x / 2.0,
+          (*rows as f32 - 1.0) * spacing.y / 2.0,
+        );
+
+        (0..*rows).flat_map(|row| (0..*columns).map(move |column| (column, row)))
+          .map(|(column, row)| {
+            let position = Vector3::new(
+              column as f32 * spacing.x - origin.x,
+              0.0,
+              row as f32 * spacing.y - origin.y,
+            );
+
+            instance_at(position, Quaternion::one())
+          })
+          .collect()
+      }
+      InstanceModifier::Radial { count, radius, axis } => {
+        let axis = axis.normalize();
+        let start = perpendicular(axis) * *radius;
+
+        (0..*count)
+          .map(|i| {
+            let rotation = Quaternion::from_axis_angle(axis, Rad(2.0 * PI * i as f32 / *count as f32));
+
+            instance_at(rotation.rotate_vector(start), rotation)
+          })
+          .collect()
+      }
+      InstanceModifier::Curve { points, count, align } => {
+        sample_evenly(&catmull_rom(points, CURVE_SAMPLES), *count)
+          .into_iter()
+          .map(|(position, tangent)| {
+            let rotation = if *align && tangent.magnitude2() > 0.0 {
+              Quaternion::from_arc(Vector3::unit_z(), tangent.normalize(), None)
+            } else {
+              Quaternion::one()
+            };
+
+            instance_at(position, rotation)
+          })
+          .collect()
+      }
+    };
+
+    apply_jitter(&mut instances, jitter);
+    instances
+  }
+}
+
+/// Points along a Catmull-Rom curve that passes through every control
+/// point, with `samples` points per span. The end points are repeated so the
+/// curve reaches both ends.
+pub fn catmull_rom(points: &[Vector3<f32>], samples: u32) -> Vec<Vector3<f32>> {
+  if points.len() < 3 || samples == 0 {
+    return points.to_vec();
+  }
+
+  let last = points.len() - 1;
+  let mut curve = Vec::with_capacity(last * samples as usize + 1);
+
+  for span in 0..last {
+    let p0 = points[span.saturating_sub(1)];
+    let p1 = points[span];
+    let p2 = points[span + 1];
+    let p3 = points[(span + 2).min(last)];
+
+    for sample in 0..samples {
+      let t = sample as f32 / samples as f32;
+      let t2 = t * t;
+      let t3 = t2 * t;
+
+      curve.push(
+        (p1 * 2.0
+          + (p2 - p0) * t
+          + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+          + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+          * 0.5,
+      );
+    }
+  }
+
+  curve.push(points[last]);
+  curve
+}
+
+/// `count` points spaced evenly by distance along the polyline, each with
+/// the direction of the segment it lies on. Both ends are included when
+/// there is more than one point.
+pub fn sample_evenly(polyline: &[Vector3<f32>], count: u32) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+  if polyline.is_empty() || count == 0 {
+    return Vec::new();
+  }
+  if polyline.len() == 1 {
+    return vec![(polyline[0], Vector3::new(0.0, 0.0, 0.0)); count as usize];
+  }
+
+  let lengths = polyline.windows(2).map(|segment| (segment[1] - segment[0]).magnitude()).collect::<Vec<_>>();
+  let total = lengths.iter().sum::<f32>();
+  let step = if count > 1 { total / (count - 1) as f32 } else { 0.0 };
+  let mut samples = Vec::with_capacity(count as usize);
+  let mut segment = 0;
+  let mut start = 0.0;
+
+  for i in 0..count {
+    let distance = step * i as f32;
+
+    while segment + 1 < lengths.len() && start + lengths[segment] < distance {
+      start += lengths[segment];
+      segment += 1;
+    }
+
+    let direction = polyline[segment + 1] - polyline[segment];
+    let t = if lengths[segment] > 0.0 {
+      ((distance - start) / lengths[segment]).clamp(0.0, 1.0)
+    } else {
+      0.0
+    };
+
+    samples.push((polyline[segment] + direction * t, direction));
+  }
+
+  samples
+}
+
+fn apply_jitter(instances: &mut [Instance], jitter: &Jitter) {
+  if jitter.rotation.0 == 0.0 && jitter.scale == 0.0 {
+    return;
+  }
+
+  let mut rng = ChaCha8Rng::seed_from_u64(jitter.seed);
+
+  for instance in instances.iter_mut() {
+    let angle = rng.gen_range(-1.0..=1.0) * jitter.rotation.0;
+    let scale = 1.0 + rng.gen_range(-1.0..=1.0) * jitter.scale;
+
+    instance.rotation = instance.rotation * Quaternion::from_angle_y(Rad(angle));
+    instance.scale *= scale.max(0.0);
+  }
+}
+
+fn instance_at(position: Vector3<f32>, rotation: Quaternion<f32>) -> Instance {
+  Instance {
+    position,
+    rotation,
+    ..Default::default()
+  }
+}
+
+/// Unit vector at right angles to `axis`.
+fn perpendicular(axis: Vector3<f32>) -> Vector3<f32> {
+  let other = if axis.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+
+  axis.cross(other).normalize()
+}
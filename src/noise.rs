@@ -0,0 +1,233 @@
+use cgmath::{InnerSpace, Vector2};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::PI;
+
+/// Shift applied to each octave so the octaves do not all line up at the
+/// origin, where every lattice noise is zero or at a feature point.
+const OCTAVE_OFFSET: f32 = 17.31;
+/// Offsets of the two noise fields that displace the sample point when
+/// domain warping, chosen so the fields are unrelated.
+const WARP_OFFSETS: [Vector2<f32>; 2] = [Vector2::new(31.7, 11.3), Vector2::new(47.1, 93.9)];
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum NoiseKind {
+  /// Gradient noise on a square lattice.
+  Perlin,
+  /// Gradient noise on a triangular lattice, with fewer axis-aligned
+  /// artifacts than Perlin.
+  Simplex,
+  /// Smoothly interpolated random values at the lattice points.
+  Value,
+  /// Distance to the nearest of one random point per cell, giving cells
+  /// that look like cracked ground or dunes.
+  Worley,
+}
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum Fractal {
+  /// Octaves added together, each at a higher frequency and lower
+  /// amplitude than the last.
+  Fbm,
+  /// Octaves folded around zero so their creases become sharp ridges.
+  Ridged,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct NoiseSettings {
+  pub kind: NoiseKind,
+  pub fractal: Fractal,
+  /// Features per world unit of the first octave.
+  pub frequency: f32,
+  pub octaves: u32,
+  /// Frequency multiplier from one octave to the next.
+  pub lacunarity: f32,
+  /// Amplitude multiplier from one octave to the next.
+  pub gain: f32,
+  /// How far, in world units, the sample point is pushed around by two
+  /// more noise fields before sampling. Zero turns domain warping off.
+  pub warp: f32,
+  pub seed: u64,
+}
+
+impl Default for NoiseSettings {
+  fn default() -> Self {
+    Self {
+      kind: NoiseKind::Perlin,
+      fractal: Fractal::Fbm,
+      frequency: 0.15,
+      octaves: 4,
+      lacunarity: 2.0,
+      gain: 0.5,
+      warp: 0.0,
+      seed: 0,
+    }
+  }
+}
+
+/// Seeded 2D coherent noise. Every kind is built on the same shuffled
+/// permutation table, so one seed gives one terrain whatever the kind.
+pub struct Noise {
+  settings: NoiseSettings,
+  permutation: [u8; 512],
+}
+
+impl Noise {
+  pub fn new(settings: NoiseSettings) -> Self {
+    let mut table = (0..=255).collect::<Vec<u8>>();
+    let mut permutation = [0; 512];
+
+    table.shuffle(&mut ChaCha8Rng::seed_from_u64(settings.seed));
+
+    for (i, value) in permutation.iter_mut().enumerate() {
+      *value = table[i % 256];
+    }
+
+    Self { settings, permutation }
+  }
+
+  /// Noise at a point, roughly between 0 and 1.
+  pub fn sample(&self, x: f32, y: f32) -> f32 {
+    let mut point = Vector2::new(x, y);
+
+    if self.settings.warp != 0.0 {
+      let offset = Vector2::new(
+        self.fractal(point + WARP_OFFSETS[0]),
+        self.fractal(point + WARP_OFFSETS[1]),
+      );
+
+      point += offset * self.settings.warp;
+    }
+
+    (self.fractal(point) * 0.5 + 0.5).clamp(0.0, 1.0)
+  }
+
+  /// Sum of the octaves, normalized to roughly -1 to 1.
+  fn fractal(&self, point: Vector2<f32>) -> f32 {
+    let mut frequency = self.settings.frequency;
+    let mut amplitude = 1.0;
+    let mut sum = 0.0;
+    let mut total = 0.0;
+
+    for octave in 0..self.settings.octaves.max(1) {
+      let offset = OCTAVE_OFFSET * octave as f32;
+      let value = self.base(point.x * frequency + offset, point.y * frequency + offset);
+      let value = match self.settings.fractal {
+        Fractal::Fbm => value,
+        Fractal::Ridged => {
+          let ridge = 1.0 - value.abs();
+
+          ridge * ridge * 2.0 - 1.0
+        }
+      };
+
+      sum += value * amplitude;
+      total += amplitude;
+      frequency *= self.settings.lacunarity;
+      amplitude *= self.settings.gain;
+    }
+
+    if total > 0.0 { sum / total } else { 0.0 }
+  }
+
+  /// One octave of the chosen kind, roughly between -1 and 1.
+  fn base(&self, x: f32, y: f32) -> f32 {
+    match self.settings.kind {
+      NoiseKind::Perlin => self.perlin(x, y),
+      NoiseKind::Simplex => self.simplex(x, y),
+      NoiseKind::Value => self.value(x, y),
+      NoiseKind::Worley => self.worley(x, y),
+    }
+  }
+
+  fn hash(&self, x: i32, y: i32) -> u8 {
+    self.permutation[self.permutation[(x & 255) as usize] as usize + (y & 255) as usize]
+  }
+
+  /// Unit gradient at a lattice point, one of eight directions.
+  fn gradient(&self, x: i32, y: i32) -> Vector2<f32> {
+    let angle = (self.hash(x, y) & 7) as f32 * PI / 4.0;
+
+    Vector2::new(angle.cos(), angle.sin())
+  }
+
+  fn perlin(&self, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let corner = |dx: i32, dy: i32| {
+      self.gradient(x0 + dx, y0 + dy).dot(Vector2::new(fx - dx as f32, fy - dy as f32))
+    };
+    let (u, v) = (fade(fx), fade(fy));
+    let bottom = lerp(corner(0, 0), corner(1, 0), u);
+    let top = lerp(corner(0, 1), corner(1, 1), u);
+
+    // Unit gradients reach at most half the diagonal of a cell.
+    lerp(bottom, top, v) * std::f32::consts::SQRT_2
+  }
+
+  fn simplex(&self, x: f32, y: f32) -> f32 {
+    let skew = 0.5 * (3.0_f32.sqrt() - 1.0);
+    let unskew = (3.0 - 3.0_f32.sqrt()) / 6.0;
+    let s = (x + y) * skew;
+    let (i, j) = ((x + s).floor() as i32, (y + s).floor() as i32);
+    let t = (i + j) as f32 * unskew;
+    let first = Vector2::new(x - (i as f32 - t), y - (j as f32 - t));
+    let (i1, j1) = if first.x > first.y { (1, 0) } else { (0, 1) };
+    let corners = [
+      (i, j, first),
+      (i + i1, j + j1, first - Vector2::new(i1 as f32, j1 as f32) + Vector2::new(unskew, unskew)),
+      (i + 1, j + 1, first - Vector2::new(1.0, 1.0) + Vector2::new(2.0 * unskew, 2.0 * unskew)),
+    ];
+    let sum = corners.iter().map(|&(ci, cj, offset)| {
+      let falloff = 0.5 - offset.magnitude2();
+
+      if falloff > 0.0 {
+        falloff.powi(4) * self.gradient(ci, cj).dot(offset)
+      } else {
+        0.0
+      }
+    }).sum::<f32>();
+
+    (sum * 99.0).clamp(-1.0, 1.0)
+  }
+
+  fn value(&self, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let corner = |dx: i32, dy: i32| self.hash(x0 + dx, y0 + dy) as f32 / 127.5 - 1.0;
+    let (u, v) = (fade(x - x.floor()), fade(y - y.floor()));
+    let bottom = lerp(corner(0, 0), corner(1, 0), u);
+    let top = lerp(corner(0, 1), corner(1, 1), u);
+
+    lerp(bottom, top, v)
+  }
+
+  fn worley(&self, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let point = Vector2::new(x, y);
+    let mut nearest = f32::INFINITY;
+
+    for dy in -1..=1 {
+      for dx in -1..=1 {
+        let (cx, cy) = (x0 + dx, y0 + dy);
+        let feature = Vector2::new(
+          cx as f32 + self.hash(cx, cy) as f32 / 255.0,
+          cy as f32 + self.hash(cy, cx + 1) as f32 / 255.0,
+        );
+
+        nearest = nearest.min((feature - point).magnitude());
+      }
+    }
+
+    nearest.min(1.0) * 2.0 - 1.0
+  }
+}
+
+/// Quintic smoothstep, which keeps the second derivative continuous across
+/// cell edges so lighting shows no creases.
+fn fade(t: f32) -> f32 {
+  t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+  a + (b - a) * t
+}
@@ -0,0 +1,220 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, Vector4};
+
+use crate::{
+  bounds::Aabb,
+  model::Model,
+  transform::normal_matrix,
+};
+
+const EPSILON: f32 = 1e-7;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+  pub origin: Point3<f32>,
+  pub direction: Vector3<f32>,
+}
+
+impl Ray {
+  pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+    Self {
+      origin,
+      direction: direction.normalize(),
+    }
+  }
+
+  /// Ray from the near plane through a pixel, given the combined view and
+  /// projection matrix used to render the frame.
+  pub fn from_screen(
+    view_proj: Matrix4<f32>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+  ) -> Option<Self> {
+    let inverse = view_proj.invert()?;
+    let ndc_x = 2.0 * x / width - 1.0;
+    let ndc_y = 1.0 - 2.0 * y / height;
+    let near = Point3::from_homogeneous(inverse * Vector4::new(ndc_x, ndc_y, 0.0, 1.0));
+    let far = Point3::from_homogeneous(inverse * Vector4::new(ndc_x, ndc_y, 1.0, 1.0));
+
+    Some(Self::new(near, far - near))
+  }
+
+  /// Ray moved by the matrix, usually the inverse of a world matrix to
+  /// bring it into local space. The direction is renormalized, so local hit
+  /// distances are not world distances.
+  pub fn transform(&self, matrix: Matrix4<f32>) -> Self {
+    Self::new(matrix.transform_point(self.origin), matrix.transform_vector(self.direction))
+  }
+
+  pub fn at(&self, distance: f32) -> Point3<f32> {
+    self.origin + self.direction * distance
+  }
+
+  /// Slab test against the box, returning the entry distance if the ray
+  /// enters it before `max_distance`.
+  pub fn intersect_aabb(&self, aabb: &Aabb, max_distance: f32) -> Option<f32> {
+    let mut near = 0.0_f32;
+    let mut far = max_distance;
+
+    for axis in 0..3 {
+      let inverse = 1.0 / self.direction[axis];
+      let mut t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+      let mut t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+
+      if inverse < 0.0 {
+        std::mem::swap(&mut t0, &mut t1);
+      }
+
+      // NaN from a zero direction component on the slab boundary fails
+      // these comparisons and leaves the interval untouched.
+      if t0 > near {
+        near = t0;
+      }
+      if t1 < far {
+        far = t1;
+      }
+      if near > far {
+        return None;
+      }
+    }
+
+    Some(near)
+  }
+
+  /// Möller–Trumbore intersection. Returns the distance and the barycentric
+  /// weights of `v1` and `v2`; both sides of the triangle are hit.
+  pub fn intersect_triangle(
+    &self,
+    v0: Point3<f32>,
+    v1: Point3<f32>,
+    v2: Point3<f32>,
+  ) -> Option<(f32, f32, f32)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = self.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+
+    if determinant.abs() < EPSILON {
+      return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let s = self.origin - v0;
+    let u = s.dot(p) * inverse;
+
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = self.direction.dot(q) * inverse;
+
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+
+    let distance = edge2.dot(q) * inverse;
+
+    if distance > EPSILON {
+      Some((distance, u, v))
+    } else {
+      None
+    }
+  }
+}
+
+/// Closest intersection of a ray with a triangle of a mesh.
+#[derive(Copy, Clone, Debug)]
+pub struct TriangleHit {
+  pub triangle: usize,
+  pub barycentrics: Vector3<f32>,
+  pub distance: f32,
+  pub normal: Vector3<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+  pub model: usize,
+  pub instance: usize,
+  pub mesh: usize,
+  pub triangle: usize,
+  pub barycentrics: Vector3<f32>,
+  pub distance: f32,
+  pub normal: Vector3<f32>,
+  pub position: Point3<f32>,
+}
+
+/// Finds the closest hit of the ray against every instance of every model.
+pub fn cast(models: &[Model], ray: &Ray) -> Option<RayHit> {
+  let mut closest: Option<RayHit> = None;
+
+  for (model_index, model) in models.iter().enumerate() {
+    for instance in 0..model.instances().len() {
+      let local_ray = match model.inverse_instance_matrix(instance) {
+        Some(inverse) => ray.transform(inverse),
+        None => continue,
+      };
+
+      if local_ray.intersect_aabb(&model.aabb, f32::INFINITY).is_none() {
+        continue;
+      }
+
+      if let Some((mesh, hit)) = model.raycast(&local_ray) {
+        let matrix = model.instance_matrix(instance);
+        let position = matrix.transform_point(local_ray.at(hit.distance));
+        let distance = (position - ray.origin).magnitude();
+
+        if distance < closest.map_or(f32::INFINITY, |hit| hit.distance) {
+          closest = Some(RayHit {
+            model: model_index,
+            instance,
+            mesh,
+            triangle: hit.triangle,
+            barycentrics: hit.barycentrics,
+            distance,
+            normal: (normal_matrix(matrix) * hit.normal).normalize(),
+            position,
+          });
+        }
+      }
+    }
+  }
+
+  closest
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CORNERS: [Point3<f32>; 3] = [
+    Point3::new(0.0, 0.0, 0.0),
+    Point3::new(1.0, 0.0, 0.0),
+    Point3::new(0.0, 0.0, 1.0),
+  ];
+
+  fn cast_at(origin: Point3<f32>, direction: Vector3<f32>) -> Option<(f32, f32, f32)> {
+    Ray::new(origin, direction).intersect_triangle(CORNERS[0], CORNERS[1], CORNERS[2])
+  }
+
+  #[test]
+  fn hits_a_triangle_from_either_side() {
+    let (distance, u, v) = cast_at(Point3::new(0.25, 2.0, 0.5), -Vector3::unit_y()).unwrap();
+
+    assert!((distance - 2.0).abs() < 1e-6);
+    assert!((u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+
+    let (distance, _, _) = cast_at(Point3::new(0.25, -3.0, 0.25), Vector3::unit_y()).unwrap();
+
+    assert!((distance - 3.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn misses_a_triangle() {
+    // Past the long edge, behind the origin and along the plane.
+    assert!(cast_at(Point3::new(0.75, 1.0, 0.75), -Vector3::unit_y()).is_none());
+    assert!(cast_at(Point3::new(0.25, 1.0, 0.25), Vector3::unit_y()).is_none());
+    assert!(cast_at(Point3::new(-1.0, 0.0, 0.25), Vector3::unit_x()).is_none());
+  }
+}
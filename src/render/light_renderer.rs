@@ -49,6 +49,7 @@ impl LightRenderer {
     light_bind_group: &'a wgpu::BindGroup,
   ) {
     render_pass.set_pipeline(&self.render_pipeline);
+    render_pass.set_vertex_buffer(1, model.instance_buffer().slice(..));
     render_pass.draw_light_model(
       model,
       camera_bind_group,
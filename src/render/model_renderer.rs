@@ -1,10 +1,10 @@
 use crate::{
   draw::DrawModel,
+  material::Material,
   render::create_render_pipeline,
 };
 
 pub struct ModelRenderer {
-  num_instances: u32,
   render_pipeline: wgpu::RenderPipeline,
 }
 
@@ -38,27 +38,37 @@ impl ModelRenderer {
       )
     };
 
-    Self {
-      num_instances: 1,
-      render_pipeline,
-    }
+    Self { render_pipeline }
   }
 
   pub fn render<'a>(
     &'a self,
     render_pass: &mut wgpu::RenderPass<'a>,
     model: &'a crate::model::Model,
+    material_override: Option<&'a Material>,
     ambient_bind_group: &'a wgpu::BindGroup,
     camera_bind_group: &'a wgpu::BindGroup,
     light_bind_group: &'a wgpu::BindGroup,
   ) {
     render_pass.set_pipeline(&self.render_pipeline);
-    render_pass.draw_model_instanced(
-      model,
-      0..self.num_instances,
-      ambient_bind_group,
-      camera_bind_group,
-      light_bind_group,
-    );
+    render_pass.set_vertex_buffer(1, model.instance_buffer().slice(..));
+
+    match material_override {
+      Some(material) => render_pass.draw_model_instanced_with_material(
+        model,
+        material,
+        0..model.num_instances(),
+        ambient_bind_group,
+        camera_bind_group,
+        light_bind_group,
+      ),
+      None => render_pass.draw_model_instanced(
+        model,
+        0..model.num_instances(),
+        ambient_bind_group,
+        camera_bind_group,
+        light_bind_group,
+      ),
+    }
   }
 }
@@ -1,6 +1,8 @@
 use cgmath::{
     Deg,
+    Matrix4,
     Quaternion,
+    Rad,
     Rotation3,
     Vector3,
 };
@@ -10,6 +12,7 @@ use crate::{
   color::ColorUniform,
   instance::InstanceRaw,
   light::LightUniform,
+  material::Material,
   mesh::{MeshVertex, Vertex},
   model::Model,
   projection::Projection,
@@ -18,16 +21,21 @@ use crate::{
   uniform::Uniform,
 };
 
+const HIGHLIGHT_TINT: [f32; 4] = [1.6, 1.4, 0.6, 1.0];
+
 pub struct Renderer {
   ambient_uniform: Uniform<ColorUniform>,
   camera_uniform: Uniform<CameraUniform>,
   depth_texture: Texture,
+  highlight_material: Material,
   light_renderer: LightRenderer,
   light_uniform: Uniform<LightUniform>,
+  material_bind_group_layout: wgpu::BindGroupLayout,
   model_renderer: ModelRenderer,
   projection: Projection,
   render_light: bool,
   rotate_light: bool,
+  selected: Option<usize>,
 }
 
 impl Renderer {
@@ -47,6 +55,13 @@ impl Renderer {
       },
       "light",
     );
+    let material_bind_group_layout = Material::create_bind_group_layout(device);
+    let highlight_material = Material::new(
+      device,
+      "Highlight",
+      HIGHLIGHT_TINT,
+      &material_bind_group_layout,
+    );
 
     let depth_format = Some(Texture::DEPTH_FORMAT);
     let depth_texture = Texture::create_depth_texture(device, config, "depth_texture");
@@ -67,6 +82,7 @@ impl Renderer {
         &ambient_uniform.bind_group_layout,
         &camera_uniform.bind_group_layout,
         &light_uniform.bind_group_layout,
+        &material_bind_group_layout,
       ],
       config.format,
       depth_format,
@@ -78,15 +94,30 @@ impl Renderer {
       ambient_uniform,
       camera_uniform,
       depth_texture,
+      highlight_material,
       light_renderer,
       light_uniform,
+      material_bind_group_layout,
       model_renderer,
       projection,
       render_light: false,
       rotate_light: false,
+      selected: None,
     }
   }
 
+  pub fn fovy(&self) -> Rad<f32> {
+    self.projection.fovy()
+  }
+
+  pub fn material_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+    &self.material_bind_group_layout
+  }
+
+  pub fn set_selected(&mut self, selected: Option<usize>) {
+    self.selected = selected;
+  }
+
   pub fn toggle_light_render(&mut self) {
     self.render_light = !self.render_light;
   }
@@ -100,15 +131,19 @@ impl Renderer {
     self.projection.resize(config.width, config.height);
   }
 
-  pub fn render(
+  /// Draws the scene, then `overlay` on top of it with a cleared depth
+  /// buffer so that it is never hidden by the models.
+  pub fn render<'a, I>(
     &mut self,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     view: &wgpu::TextureView,
     light_model: &Model,
-    models: &Vec<Model>,
-    instance_buffer: &wgpu::Buffer,
-  ) {
+    models: I,
+    overlay: Option<&Model>,
+  ) where
+    I: IntoIterator<Item = &'a Model>,
+  {
       let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
         label: Some("Render Encoder"),
       });
@@ -134,7 +169,6 @@ impl Renderer {
             stencil_ops: None,
           }),
       });
-      render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
 
       if self.render_light {
         self.light_renderer.render(
@@ -145,16 +179,55 @@ impl Renderer {
         );
       }
 
-      for model in models {
+      for (i, model) in models.into_iter().enumerate() {
+        let material_override = if self.selected == Some(i) {
+          Some(&self.highlight_material)
+        } else {
+          None
+        };
+
         self.model_renderer.render(
           &mut render_pass,
           model,
+          material_override,
           &self.ambient_uniform.bind_group,
           &self.camera_uniform.bind_group,
           &self.light_uniform.bind_group,
         );
       }
     }
+    if let Some(overlay) = overlay {
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Overlay Render Pass"),
+        color_attachments: &[
+          wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+              load: wgpu::LoadOp::Load,
+              store: true,
+            },
+          }
+        ],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &self.depth_texture.view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: true,
+          }),
+          stencil_ops: None,
+        }),
+      });
+
+      self.model_renderer.render(
+        &mut render_pass,
+        overlay,
+        None,
+        &self.ambient_uniform.bind_group,
+        &self.camera_uniform.bind_group,
+        &self.light_uniform.bind_group,
+      );
+    }
     queue.submit(std::iter::once(encoder.finish()));
   }
 
@@ -172,6 +245,10 @@ impl Renderer {
     queue.write_buffer(&self.light_uniform.buffer, 0, bytemuck::cast_slice(&[self.light_uniform.uniform]));
   }
 
+  pub fn view_projection<C: Camera>(&self, camera: &C) -> Matrix4<f32> {
+    self.projection.calc_matrix() * camera.projection()
+  }
+
   pub fn update_camera_uniform<C: Camera>(&mut self, camera: &C) {
     self.camera_uniform.uniform.update_view_proj(camera, &self.projection);
   }
@@ -20,6 +20,12 @@ struct Light {
 [[group(2), binding(0)]]
 var<uniform> light: Light;
 
+struct Material {
+    tint: vec4<f32>;
+};
+[[group(3), binding(0)]]
+var<uniform> material: Material;
+
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] normal: vec3<f32>;
@@ -65,7 +71,7 @@ fn vs_main(
     var out: VertexOutput;
     out.clip_position = camera.view_proj * world_position;
     out.normal = world_normal;
-    out.position = model.position;
+    out.position = world_position.xyz;
     out.color = model.color;
 
     return out;
@@ -81,9 +87,10 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     let light_dir = normalize(light.position - in.position);
 
     let diffuse_strength = dot(in.normal, light_dir);
-    let diffuse_color = in.color.zyx * diffuse_strength;
+    let base_color = in.color * material.tint;
+    let diffuse_color = base_color.xyz * diffuse_strength;
 
     let color = ambient_color + diffuse_color;
 
-    return vec4<f32>(color, in.color.a);
+    return vec4<f32>(color, base_color.a);
 }
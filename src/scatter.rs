@@ -0,0 +1,237 @@
+use cgmath::{InnerSpace, Point3, Quaternion, Rad, Rotation3, Transform, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{collections::HashMap, f32::consts::PI};
+
+use crate::{
+  instance::Instance,
+  model::Model,
+};
+
+/// Candidate points thrown per copy asked for before giving up on filling
+/// the surface.
+const ATTEMPTS_PER_COPY: usize = 30;
+
+/// Options for `scatter`. Copies are kept at least `spacing` apart and
+/// placed only where the surface is no steeper than `max_slope` and its
+/// height lies between `min_height` and `max_height`.
+#[derive(Copy, Clone, Debug)]
+pub struct ScatterSettings {
+  /// Copies wanted per unit of surface area, before spacing and the
+  /// limits remove some of them.
+  pub density: f32,
+  pub spacing: f32,
+  pub max_slope: Rad<f32>,
+  pub min_height: f32,
+  pub max_height: f32,
+  /// Tilts each copy so its Y axis follows the surface normal instead of
+  /// pointing straight up.
+  pub align_to_normal: bool,
+  pub seed: u64,
+}
+
+impl Default for ScatterSettings {
+  fn default() -> Self {
+    Self {
+      density: 1.0,
+      spacing: 0.5,
+      max_slope: Rad(PI / 2.0),
+      min_height: f32::NEG_INFINITY,
+      max_height: f32::INFINITY,
+      align_to_normal: false,
+      seed: 0,
+    }
+  }
+}
+
+struct Triangle {
+  corners: [Point3<f32>; 3],
+  normal: Vector3<f32>,
+  area: f32,
+}
+
+/// Poisson-disk scatter over every instance of the target model by dart
+/// throwing: points are picked uniformly by area and dropped when they
+/// break a limit or land within `spacing` of a copy already placed. Copies
+/// come back in world space, each turned by a random angle about its Y
+/// axis.
+pub fn scatter(target: &Model, settings: &ScatterSettings) -> Vec<Instance> {
+  scatter_over(&world_triangles(target), settings)
+}
+
+fn scatter_over(triangles: &[Triangle], settings: &ScatterSettings) -> Vec<Instance> {
+  let total_area = triangles.iter().map(|triangle| triangle.area).sum::<f32>();
+
+  if total_area <= 0.0 || settings.density <= 0.0 {
+    return Vec::new();
+  }
+
+  let wanted = (total_area * settings.density).round() as usize;
+  let cumulative_area = triangles.iter()
+    .scan(0.0, |sum, triangle| {
+      *sum += triangle.area;
+      Some(*sum)
+    })
+    .collect::<Vec<_>>();
+  let min_normal_y = settings.max_slope.0.cos();
+  let mut grid = SpacingGrid::new(settings.spacing);
+  let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+  let mut instances = Vec::with_capacity(wanted);
+
+  for _ in 0..wanted * ATTEMPTS_PER_COPY {
+    if instances.len() >= wanted {
+      break;
+    }
+
+    let pick = rng.gen_range(0.0..total_area);
+    let index = cumulative_area.partition_point(|&area| area <= pick).min(triangles.len() - 1);
+    let triangle = &triangles[index];
+    let position = triangle.sample(&mut rng);
+
+    if triangle.normal.y < min_normal_y
+      || position.y < settings.min_height
+      || position.y > settings.max_height
+      || !grid.insert(position)
+    {
+      continue;
+    }
+
+    let up = if settings.align_to_normal { triangle.normal } else { Vector3::unit_y() };
+    let yaw = Quaternion::from_angle_y(Rad(rng.gen_range(0.0..2.0 * PI)));
+
+    instances.push(Instance {
+      position: Vector3::new(position.x, position.y, position.z),
+      rotation: Quaternion::from_arc(Vector3::unit_y(), up, None) * yaw,
+      ..Default::default()
+    });
+  }
+
+  instances
+}
+
+impl Triangle {
+  /// Uniformly distributed point on the triangle.
+  fn sample(&self, rng: &mut ChaCha8Rng) -> Point3<f32> {
+    let mut u = rng.gen_range(0.0..1.0);
+    let mut v = rng.gen_range(0.0..1.0);
+
+    if u + v > 1.0 {
+      u = 1.0 - u;
+      v = 1.0 - v;
+    }
+
+    self.corners[0] + (self.corners[1] - self.corners[0]) * u + (self.corners[2] - self.corners[0]) * v
+  }
+}
+
+/// Uniform grid of cells `spacing` wide, so only the neighbouring cells
+/// need checking for points that are too close.
+struct SpacingGrid {
+  spacing: f32,
+  cells: HashMap<[i32; 3], Vec<Point3<f32>>>,
+}
+
+impl SpacingGrid {
+  fn new(spacing: f32) -> Self {
+    Self {
+      spacing,
+      cells: HashMap::new(),
+    }
+  }
+
+  /// Adds the point unless another one lies within the spacing.
+  fn insert(&mut self, point: Point3<f32>) -> bool {
+    if self.spacing <= 0.0 {
+      return true;
+    }
+
+    let cell = [
+      (point.x / self.spacing).floor() as i32,
+      (point.y / self.spacing).floor() as i32,
+      (point.z / self.spacing).floor() as i32,
+    ];
+    let spacing2 = self.spacing * self.spacing;
+
+    for dx in -1..=1 {
+      for dy in -1..=1 {
+        for dz in -1..=1 {
+          let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+
+          if let Some(points) = self.cells.get(&neighbour) {
+            if points.iter().any(|other| (other - point).magnitude2() < spacing2) {
+              return false;
+            }
+          }
+        }
+      }
+    }
+
+    self.cells.entry(cell).or_default().push(point);
+    true
+  }
+}
+
+fn world_triangles(model: &Model) -> Vec<Triangle> {
+  let mut triangles = Vec::new();
+
+  for instance in 0..model.instances().len() {
+    let matrix = model.instance_matrix(instance);
+
+    for mesh in &model.meshes {
+      for triangle in mesh.indices.chunks_exact(3) {
+        if triangle.iter().any(|&i| i as usize >= mesh.vertices.len()) {
+          continue;
+        }
+
+        let corners = [
+          matrix.transform_point(Point3::from(mesh.vertices[triangle[0] as usize].position)),
+          matrix.transform_point(Point3::from(mesh.vertices[triangle[1] as usize].position)),
+          matrix.transform_point(Point3::from(mesh.vertices[triangle[2] as usize].position)),
+        ];
+        let cross = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let length = cross.magnitude();
+
+        if length > 0.0 {
+          triangles.push(Triangle {
+            corners,
+            normal: cross / length,
+            area: length / 2.0,
+          });
+        }
+      }
+    }
+  }
+
+  triangles
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::instance_bytes;
+
+  fn square(size: f32) -> Vec<Triangle> {
+    let corners = [
+      Point3::new(0.0, 0.0, 0.0),
+      Point3::new(0.0, 0.0, size),
+      Point3::new(size, 0.0, size),
+      Point3::new(size, 0.0, 0.0),
+    ];
+
+    [[0, 1, 2], [0, 2, 3]].iter().map(|triangle| Triangle {
+      corners: triangle.map(|i| corners[i]),
+      normal: Vector3::unit_y(),
+      area: size * size / 2.0,
+    }).collect()
+  }
+
+  #[test]
+  fn scatter_is_the_same_for_a_seed() {
+    let triangles = square(10.0);
+    let settings = ScatterSettings { seed: 7, ..Default::default() };
+    let first = scatter_over(&triangles, &settings);
+
+    assert!(!first.is_empty());
+    assert_eq!(instance_bytes(&first), instance_bytes(&scatter_over(&triangles, &settings)));
+  }
+}
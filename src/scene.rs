@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use crate::{
+  instance::Instance,
+  transform::Transform,
+};
+
+pub type NodeId = usize;
+
+pub struct SceneNode {
+  pub name: String,
+  pub transform: Transform,
+  pub model: Option<usize>,
+  pub children: Vec<NodeId>,
+  pub parent: Option<NodeId>,
+}
+
+/// Hierarchy of named nodes with local transforms. Nodes that reference a
+/// model, by index into the state's model list, become instances of it.
+///
+/// Nodes can only be parented to nodes that already exist, so parents always
+/// come before their children and world transforms resolve in one pass.
+#[derive(Default)]
+pub struct SceneGraph {
+  /// Models whose instances changed since they were last taken with
+  /// `take_changed_instances`.
+  changed: HashSet<usize>,
+  nodes: Vec<SceneNode>,
+}
+
+impl SceneGraph {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add_node(
+    &mut self,
+    name: &str,
+    transform: Transform,
+    model: Option<usize>,
+    parent: Option<NodeId>,
+  ) -> NodeId {
+    let id = self.nodes.len();
+
+    if let Some(parent) = parent {
+      self.nodes[parent].children.push(id);
+    }
+
+    self.changed.extend(model);
+    self.nodes.push(SceneNode {
+      name: String::from(name),
+      transform,
+      model,
+      children: Vec::new(),
+      parent,
+    });
+
+    id
+  }
+
+  /// Whether any node places copies of the model.
+  pub fn contains_model(&self, model: usize) -> bool {
+    self.nodes.iter().any(|node| node.model == Some(model))
+  }
+
+  pub fn find(&self, name: &str) -> Option<NodeId> {
+    self.nodes.iter().position(|node| node.name == name)
+  }
+
+  /// Topmost ancestor of the first node that places copies of the model,
+  /// which moves the whole group the model belongs to.
+  pub fn model_root(&self, model: usize) -> Option<NodeId> {
+    let mut id = self.nodes.iter().position(|node| node.model == Some(model))?;
+
+    while let Some(parent) = self.nodes[id].parent {
+      id = parent;
+    }
+
+    Some(id)
+  }
+
+  /// Instances for every model referenced by a node, indexed by model.
+  /// Models that no node references are `None` and keep their own instances.
+  pub fn model_instances(&self, num_models: usize) -> Vec<Option<Vec<Instance>>> {
+    let mut instances = vec![None; num_models];
+
+    for (node, world) in self.nodes.iter().zip(self.world_transforms()) {
+      if let Some(model) = node.model.filter(|&model| model < num_models) {
+        instances[model].get_or_insert_with(Vec::new).push(Instance::from(world));
+      }
+    }
+
+    instances
+  }
+
+  pub fn node(&self, id: NodeId) -> &SceneNode {
+    &self.nodes[id]
+  }
+
+  /// Detaches nodes from a model that was removed and shifts references to
+  /// the models after it.
+  pub fn remove_model(&mut self, model: usize) {
+    self.changed = self.changed.iter()
+      .filter(|&&index| index != model)
+      .map(|&index| if index > model { index - 1 } else { index })
+      .collect();
+
+    for node in self.nodes.iter_mut() {
+      node.model = match node.model {
+        Some(index) if index == model => None,
+        Some(index) if index > model => Some(index - 1),
+        other => other,
+      };
+    }
+  }
+
+  /// Moves a node relative to its parent, and with it every node below it.
+  /// The models they place are given again by `take_changed_instances`.
+  pub fn set_local_transform(&mut self, id: NodeId, transform: Transform) {
+    let mut stack = vec![id];
+
+    self.nodes[id].transform = transform;
+
+    while let Some(id) = stack.pop() {
+      self.changed.extend(self.nodes[id].model);
+      stack.extend(&self.nodes[id].children);
+    }
+  }
+
+  /// Instances from `model_instances` of the models that changed since
+  /// they were last taken, so only those are uploaded again. The others
+  /// are `None`.
+  pub fn take_changed_instances(&mut self, num_models: usize) -> Option<Vec<Option<Vec<Instance>>>> {
+    if self.changed.is_empty() {
+      return None;
+    }
+
+    let changed = std::mem::take(&mut self.changed);
+    let instances = self.model_instances(num_models).into_iter()
+      .enumerate()
+      .map(|(model, instances)| instances.filter(|_| changed.contains(&model)))
+      .collect();
+
+    Some(instances)
+  }
+
+  /// World transform of every node, in node order.
+  pub fn world_transforms(&self) -> Vec<Transform> {
+    let mut world: Vec<Transform> = Vec::with_capacity(self.nodes.len());
+
+    for node in &self.nodes {
+      let transform = match node.parent {
+        Some(parent) => world[parent].then(&node.transform),
+        None => node.transform,
+      };
+
+      world.push(transform);
+    }
+
+    world
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Vector3;
+
+  fn at(x: f32) -> Transform {
+    Transform {
+      position: Vector3::new(x, 0.0, 0.0),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn moving_a_parent_moves_its_children() {
+    let mut scene = SceneGraph::new();
+    let root = scene.add_node("Root", at(0.0), None, None);
+
+    scene.add_node("Child", at(1.0), Some(0), Some(root));
+    scene.add_node("Other", at(5.0), Some(1), None);
+    scene.take_changed_instances(2);
+
+    assert!(scene.take_changed_instances(2).is_none());
+
+    scene.set_local_transform(scene.find("Root").unwrap(), at(2.0));
+
+    let instances = scene.take_changed_instances(2).unwrap();
+
+    assert_eq!(instances[0].as_ref().unwrap()[0].position, Vector3::new(3.0, 0.0, 0.0));
+    assert!(instances[1].is_none());
+  }
+}
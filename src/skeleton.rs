@@ -0,0 +1,327 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+/// Wavefront vertices closer than this, relative to the size of the
+/// footprint, are merged.
+const MERGE_EPSILON: f32 = 1e-4;
+/// Upper bound on events per footprint vertex, in case rounding keeps a
+/// degenerate wavefront alive.
+const MAX_EVENTS_PER_VERTEX: usize = 8;
+const PARALLEL_EPSILON: f32 = 1e-6;
+
+#[derive(Copy, Clone, Debug)]
+struct Vertex {
+  position: Vector2<f32>,
+  /// Footprint edge that runs from this vertex to the next.
+  edge: usize,
+}
+
+enum Event {
+  /// An edge shrinks to nothing. The merge after every step handles it.
+  Edge,
+  /// A reflex vertex of a polygon runs into one of its other edges and
+  /// splits the polygon in two.
+  Split { polygon: usize, vertex: usize, edge: usize },
+}
+
+/// Signed area of a polygon on the XZ plane, positive when the points run
+/// from +X towards +Z.
+pub fn signed_area(points: &[Vector2<f32>]) -> f32 {
+  points.iter().enumerate().map(|(i, a)| {
+    let b = points[(i + 1) % points.len()];
+
+    a.x * b.y - b.x * a.y
+  }).sum::<f32>() / 2.0
+}
+
+/// Moves every edge of a footprint `distance` inward, or outward when
+/// negative. Edges that would shrink to nothing on the way are not removed.
+pub fn offset(footprint: &[Vector2<f32>], distance: f32) -> Vec<Vector2<f32>> {
+  let (polygon, normals) = wavefront(footprint);
+
+  positions(&advance(&polygon, &normals, distance))
+}
+
+/// Sweeps the edges of a simple polygon inward at equal speed until they
+/// meet, which traces out its straight skeleton. `footprint` must have a
+/// positive `signed_area`. The sweep starts `start` outside the footprint
+/// when negative, so a roof can overhang the walls.
+///
+/// Returns the area each edge sweeps between events as quads of `(x, t,
+/// z)`, where `t` is the distance the edge has moved. Points repeat where
+/// an edge shrinks to nothing. Every quad lies in the plane of the face
+/// of its footprint edge, so raising `t` by a slope gives a hip roof.
+pub fn sweep(footprint: &[Vector2<f32>], start: f32) -> Vec<[Vector3<f32>; 4]> {
+  let count = footprint.len();
+  let (initial, normals) = wavefront(footprint);
+  let directions = normals.iter().map(|normal| Vector2::new(normal.y, -normal.x)).collect::<Vec<_>>();
+  let size = footprint.iter().fold(0.0_f32, |size, point| size.max(point.x.abs()).max(point.y.abs()));
+  let epsilon = MERGE_EPSILON * size.max(1.0);
+  let mut polygons = vec![advance(&initial, &normals, start)];
+  let mut time = start;
+  let mut quads = Vec::new();
+
+  for _ in 0..count * MAX_EVENTS_PER_VERTEX {
+    let (delta, event) = match next_event(&polygons, &directions, &normals, epsilon) {
+      Some(event) => event,
+      None => break,
+    };
+
+    for polygon in &mut polygons {
+      let moved = advance(polygon, &normals, delta);
+
+      for i in 0..polygon.len() {
+        let j = (i + 1) % polygon.len();
+        let point = |position: Vector2<f32>, t: f32| Vector3::new(position.x, t, position.y);
+
+        quads.push([
+          point(polygon[i].position, time),
+          point(polygon[j].position, time),
+          point(moved[j].position, time + delta),
+          point(moved[i].position, time + delta),
+        ]);
+      }
+
+      *polygon = moved;
+    }
+
+    time += delta;
+
+    if let Event::Split { polygon, vertex, edge } = event {
+      let (first, second) = split(&polygons.swap_remove(polygon), vertex, edge);
+
+      polygons.push(first);
+      polygons.push(second);
+    }
+
+    for polygon in &mut polygons {
+      merge_close(polygon, epsilon);
+    }
+
+    polygons.retain(|polygon| polygon.len() >= 3 && signed_area(&positions(polygon)) > epsilon * epsilon);
+  }
+
+  quads
+}
+
+/// Moves every vertex so each edge travels `distance` along its inward
+/// normal.
+fn advance(polygon: &[Vertex], normals: &[Vector2<f32>], distance: f32) -> Vec<Vertex> {
+  (0..polygon.len()).map(|i| Vertex {
+    position: polygon[i].position + velocity(polygon, normals, i) * distance,
+    edge: polygon[i].edge,
+  }).collect()
+}
+
+fn merge_close(polygon: &mut Vec<Vertex>, epsilon: f32) {
+  let mut i = 0;
+
+  while polygon.len() > 1 && i < polygon.len() {
+    let j = (i + 1) % polygon.len();
+
+    if (polygon[j].position - polygon[i].position).magnitude() < epsilon {
+      // The edge leaving `i` has collapsed, so the merged vertex keeps the
+      // edge leaving `j`.
+      polygon[j].position = (polygon[i].position + polygon[j].position) / 2.0;
+      polygon.remove(i);
+      i = 0;
+    } else {
+      i += 1;
+    }
+  }
+}
+
+/// Earliest event among all polygons and the distance the wavefront moves
+/// until it happens.
+fn next_event(
+  polygons: &[Vec<Vertex>],
+  directions: &[Vector2<f32>],
+  normals: &[Vector2<f32>],
+  epsilon: f32,
+) -> Option<(f32, Event)> {
+  let mut earliest: Option<(f32, Event)> = None;
+  let mut consider = |time: f32, event: Event| {
+    if time < earliest.as_ref().map_or(f32::INFINITY, |(earliest, _)| *earliest) {
+      earliest = Some((time.max(0.0), event));
+    }
+  };
+
+  for (index, polygon) in polygons.iter().enumerate() {
+    let count = polygon.len();
+    let velocities = (0..count).map(|i| velocity(polygon, normals, i)).collect::<Vec<_>>();
+
+    for i in 0..count {
+      let j = (i + 1) % count;
+      let direction = directions[polygon[i].edge];
+      let length = (polygon[j].position - polygon[i].position).dot(direction);
+      let rate = (velocities[j] - velocities[i]).dot(direction);
+
+      if rate < -PARALLEL_EPSILON {
+        consider(-length / rate, Event::Edge);
+      }
+
+      let previous = (i + count - 1) % count;
+      let incoming = directions[polygon[previous].edge];
+
+      if incoming.perp_dot(direction) > -PARALLEL_EPSILON {
+        continue;
+      }
+
+      for edge in (0..count).filter(|&edge| edge != i && edge != previous) {
+        let next = (edge + 1) % count;
+        let normal = normals[polygon[edge].edge];
+        let distance = normal.dot(polygon[i].position - polygon[edge].position);
+        let closing = 1.0 - normal.dot(velocities[i]);
+
+        if distance < -epsilon || closing < PARALLEL_EPSILON {
+          continue;
+        }
+
+        let time = distance / closing;
+        let hit = polygon[i].position + velocities[i] * time;
+        let edge_direction = directions[polygon[edge].edge];
+        let from_start = (hit - (polygon[edge].position + velocities[edge] * time)).dot(edge_direction);
+        let to_end = (polygon[next].position + velocities[next] * time - hit).dot(edge_direction);
+
+        if from_start > -epsilon && to_end > -epsilon {
+          consider(time, Event::Split { polygon: index, vertex: i, edge });
+        }
+      }
+    }
+  }
+
+  earliest
+}
+
+/// Vertices of the footprint with the inward normal of each of its edges.
+fn wavefront(footprint: &[Vector2<f32>]) -> (Vec<Vertex>, Vec<Vector2<f32>>) {
+  let count = footprint.len();
+  let vertices = footprint.iter().enumerate().map(|(i, &position)| Vertex { position, edge: i }).collect();
+  let normals = (0..count).map(|i| {
+    let direction = (footprint[(i + 1) % count] - footprint[i]).normalize();
+
+    Vector2::new(-direction.y, direction.x)
+  }).collect();
+
+  (vertices, normals)
+}
+
+fn positions(polygon: &[Vertex]) -> Vec<Vector2<f32>> {
+  polygon.iter().map(|vertex| vertex.position).collect()
+}
+
+/// Splits a polygon where `vertex` touches the edge leaving `edge`. Each
+/// half keeps a copy of the vertex and a part of the edge.
+fn split(polygon: &[Vertex], vertex: usize, edge: usize) -> (Vec<Vertex>, Vec<Vertex>) {
+  let count = polygon.len();
+  let hit = polygon[vertex].position;
+  let mut first = vec![Vertex { position: hit, edge: polygon[edge].edge }];
+  let mut second = vec![Vertex { position: hit, edge: polygon[vertex].edge }];
+  let mut i = (edge + 1) % count;
+
+  while i != vertex {
+    first.push(polygon[i]);
+    i = (i + 1) % count;
+  }
+
+  i = vertex;
+
+  while i != edge {
+    i = (i + 1) % count;
+    second.push(polygon[i]);
+  }
+
+  (first, second)
+}
+
+/// Velocity of a vertex that keeps both of its edges moving inward at unit
+/// speed.
+fn velocity(polygon: &[Vertex], normals: &[Vector2<f32>], vertex: usize) -> Vector2<f32> {
+  let previous = polygon[(vertex + polygon.len() - 1) % polygon.len()].edge;
+  let (a, b) = (normals[previous], normals[polygon[vertex].edge]);
+  let determinant = a.perp_dot(b);
+
+  if determinant.abs() < PARALLEL_EPSILON {
+    return a;
+  }
+
+  Vector2::new(b.y - a.y, a.x - b.x) / determinant
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::house::load_footprint;
+
+  const EPSILON: f32 = 1e-4;
+
+  /// Ends of a ridge as `(x, t, z)`.
+  type Ridge = ([f32; 3], [f32; 3]);
+
+  fn footprint(name: &str) -> Vec<Vector2<f32>> {
+    let mut footprint = load_footprint(format!("{}/res/footprints/{}.json", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+
+    if signed_area(&footprint) < 0.0 {
+      footprint.reverse();
+    }
+
+    footprint
+  }
+
+  fn flat(points: &[Vector3<f32>]) -> Vec<Vector2<f32>> {
+    points.iter().map(|point| Vector2::new(point.x, point.z)).collect()
+  }
+
+  /// Whether the top edge of a quad runs between the two points.
+  fn has_ridge(quads: &[[Vector3<f32>; 4]], a: [f32; 3], b: [f32; 3]) -> bool {
+    let at = |point: Vector3<f32>, expected: [f32; 3]| (point - Vector3::from(expected)).magnitude() < EPSILON;
+
+    quads.iter().any(|quad| (at(quad[3], a) && at(quad[2], b)) || (at(quad[3], b) && at(quad[2], a)))
+  }
+
+  #[test]
+  fn sweeps_a_ridge_per_wing() {
+    // Faces that cover some of the footprint, the height of the top of the
+    // sweep and the ridges along the middle of each wing.
+    let cases: [(&str, usize, f32, &[Ridge]); 3] = [
+      ("l", 10, 1.0, &[
+        ([1.25, 0.75, -0.75], [-0.75, 0.75, -0.75]),
+        ([-1.0, 1.0, -0.5], [-1.0, 1.0, 0.5]),
+      ]),
+      ("t", 8, 0.75, &[
+        ([-1.75, 0.75, -0.75], [1.75, 0.75, -0.75]),
+        ([0.0, 0.75, -0.75], [0.0, 0.75, 1.25]),
+      ]),
+      ("u", 8, 0.75, &[
+        ([-1.75, 0.75, -1.25], [1.75, 0.75, -1.25]),
+        ([1.75, 0.75, -1.25], [1.75, 0.75, 1.25]),
+        ([-1.75, 0.75, -1.25], [-1.75, 0.75, 1.25]),
+      ]),
+    ];
+
+    for (name, faces, height, ridges) in cases {
+      let quads = sweep(&footprint(name), 0.0);
+      let swept = quads.iter().filter(|quad| signed_area(&flat(&quad[..])).abs() > EPSILON).count();
+      let top = quads.iter().flatten().fold(0.0_f32, |top, point| top.max(point.y));
+
+      assert_eq!(swept, faces, "{}", name);
+      assert!((top - height).abs() < EPSILON, "{} is {} high", name, top);
+
+      for &(a, b) in ridges {
+        assert!(has_ridge(&quads, a, b), "{} has no ridge from {:?} to {:?}", name, a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn roof_faces_close_over_the_footprint() {
+    for name in ["l", "t", "u"] {
+      let footprint = footprint(name);
+
+      for start in [0.0, -0.5] {
+        let area = sweep(&footprint, start).iter().map(|quad| signed_area(&flat(&quad[..]))).sum::<f32>();
+
+        assert!((area - signed_area(&offset(&footprint, start))).abs() < 1e-3, "{} from {}", name, start);
+      }
+    }
+  }
+}
@@ -0,0 +1,297 @@
+use cgmath::{InnerSpace, Rad, Vector2, Vector3};
+
+use crate::{
+  mesh::MeshBuilder,
+  model::Model,
+};
+
+pub const STAIR_STEP_MATERIAL: usize = 0;
+pub const STAIR_FRAME_MATERIAL: usize = 1;
+pub const STAIR_MATERIALS: [(&str, [f32;4]); 2] = [("Step", STEP_COLOR), ("Frame", FRAME_COLOR)];
+
+const BALUSTER_SIZE: f32 = 0.03;
+const COLUMN_SEGMENTS: u32 = 16;
+const FRAME_COLOR: [f32;4] = [0.3, 0.3, 0.32, 1.0];
+const HANDRAIL_SIZE: f32 = 0.05;
+const NEWEL_SIZE: f32 = 0.08;
+const STEP_COLOR: [f32;4] = [0.6, 0.45, 0.3, 1.0];
+/// Depth of a stringer at right angles to its slope.
+const STRINGER_DEPTH: f32 = 0.25;
+const TREAD_THICKNESS: f32 = 0.04;
+
+#[derive(clap::ArgEnum, Copy, Clone, Debug, PartialEq)]
+pub enum StairKind {
+  /// One flight climbing along +Z.
+  Straight,
+  /// Two flights joined by a square landing, turning from +Z to +X.
+  LShaped,
+  /// Wedge steps winding around a central column.
+  Spiral,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct StairSettings {
+  pub kind: StairKind,
+  pub steps: u32,
+  /// Height of each step.
+  pub rise: f32,
+  /// Depth of each step. Spiral steps use `turn` instead.
+  pub run: f32,
+  pub width: f32,
+  /// Beams under both sides of every flight, or under the outside of a
+  /// spiral.
+  pub stringers: bool,
+  pub stringer_thickness: f32,
+  pub handrails: bool,
+  /// Height of the handrails above the steps.
+  pub handrail_height: f32,
+  /// Radius of the column in the middle of a spiral staircase.
+  pub column_radius: f32,
+  /// Turn from one spiral step to the next.
+  pub turn: Rad<f32>,
+}
+
+impl Default for StairSettings {
+  fn default() -> Self {
+    Self {
+      kind: StairKind::Straight,
+      steps: 12,
+      rise: 0.18,
+      run: 0.28,
+      width: 1.0,
+      stringers: true,
+      stringer_thickness: 0.05,
+      handrails: false,
+      handrail_height: 0.9,
+      column_radius: 0.1,
+      turn: Rad(22.5_f32.to_radians()),
+    }
+  }
+}
+
+/// Staircase standing on the origin. Straight and L-shaped stairs start
+/// from the X axis and spiral stairs wind around the Y axis.
+pub fn build(settings: &StairSettings) -> MeshBuilder {
+  let mut builder = MeshBuilder::new("Stairs");
+
+  match settings.kind {
+    StairKind::Straight => {
+      add_flight(&mut builder, settings, Vector3::new(0.0, 0.0, 0.0), Vector3::unit_z(), settings.steps);
+    }
+    StairKind::LShaped => {
+      // The landing is a step of its own between the two flights.
+      let first = settings.steps.saturating_sub(1) / 2;
+      let second = settings.steps.saturating_sub(1) - first;
+      let landing = (first + 1) as f32 * settings.rise;
+      let corner = first as f32 * settings.run;
+
+      add_flight(&mut builder, settings, Vector3::new(0.0, 0.0, 0.0), Vector3::unit_z(), first);
+      add_landing(&mut builder, settings, corner, landing);
+      add_flight(
+        &mut builder,
+        settings,
+        Vector3::new(settings.width / 2.0, landing, corner + settings.width / 2.0),
+        Vector3::unit_x(),
+        second,
+      );
+    }
+    StairKind::Spiral => add_spiral(&mut builder, settings),
+  }
+
+  builder
+}
+
+/// Straight run of `count` treads from `origin`, the middle of the bottom
+/// of the first riser, climbing towards `forward`.
+fn add_flight(
+  builder: &mut MeshBuilder,
+  settings: &StairSettings,
+  origin: Vector3<f32>,
+  forward: Vector3<f32>,
+  count: u32,
+) {
+  if count == 0 {
+    return;
+  }
+
+  let across = Vector3::new(forward.z, 0.0, -forward.x);
+  let up = Vector3::unit_y();
+  let length = forward * count as f32 * settings.run;
+
+  builder.set_material(STAIR_STEP_MATERIAL);
+
+  for i in 0..count {
+    let front = origin + forward * (i as f32 * settings.run) + up * ((i + 1) as f32 * settings.rise - TREAD_THICKNESS / 2.0);
+
+    builder.add_beam(front, front + forward * settings.run, across, Vector2::new(settings.width, TREAD_THICKNESS));
+  }
+
+  builder.set_material(STAIR_FRAME_MATERIAL);
+
+  for side in [-1.0, 1.0] {
+    if settings.stringers {
+      // The top of the stringer runs along the bottom front edge of every
+      // tread.
+      let slope = (length + up * count as f32 * settings.rise).normalize();
+      let below = STRINGER_DEPTH / 2.0 / slope.dot(forward);
+      let start = origin
+        + across * side * (settings.width + settings.stringer_thickness) / 2.0
+        + up * (settings.rise - TREAD_THICKNESS - below);
+      let end = start + length + up * count as f32 * settings.rise;
+
+      builder.add_beam(start, end, across, Vector2::new(settings.stringer_thickness, STRINGER_DEPTH));
+    }
+
+    if settings.handrails {
+      let tread = |i: u32| {
+        origin
+          + across * side * (settings.width - NEWEL_SIZE) / 2.0
+          + forward * ((i as f32 + 0.5) * settings.run)
+          + up * ((i + 1) as f32 * settings.rise)
+      };
+
+      add_handrail(builder, settings, &(0..count).map(tread).collect::<Vec<_>>(), across);
+    }
+  }
+}
+
+/// Balusters standing on each of the points with a handrail along their
+/// tops and a newel post at both ends.
+fn add_handrail(builder: &mut MeshBuilder, settings: &StairSettings, points: &[Vector3<f32>], across: Vector3<f32>) {
+  let height = settings.handrail_height;
+  let rail = Vector3::unit_y() * (height + HANDRAIL_SIZE / 2.0);
+
+  for (i, &point) in points.iter().enumerate() {
+    let size = if i == 0 || i + 1 == points.len() { NEWEL_SIZE } else { BALUSTER_SIZE };
+
+    Model::add_post(builder, point, size, size, height);
+  }
+
+  for pair in points.windows(2) {
+    builder.add_beam(pair[0] + rail, pair[1] + rail, across, Vector2::new(HANDRAIL_SIZE, HANDRAIL_SIZE));
+  }
+}
+
+/// Square landing at the top of the first flight of an L-shaped staircase,
+/// with posts under its far corners and rails on its two open sides.
+fn add_landing(builder: &mut MeshBuilder, settings: &StairSettings, start: f32, top: f32) {
+  let half = settings.width / 2.0;
+  let inset = half - NEWEL_SIZE / 2.0;
+  let far = start + settings.width;
+
+  builder.set_material(STAIR_STEP_MATERIAL);
+  builder.add_box(
+    Vector3::new(-half, top - TREAD_THICKNESS, start),
+    Vector3::new(settings.width, TREAD_THICKNESS, settings.width),
+  );
+
+  builder.set_material(STAIR_FRAME_MATERIAL);
+
+  if settings.stringers {
+    for x in [-inset, inset] {
+      Model::add_post(builder, Vector3::new(x, 0.0, far - NEWEL_SIZE / 2.0), NEWEL_SIZE, NEWEL_SIZE, top - TREAD_THICKNESS);
+    }
+  }
+
+  if settings.handrails {
+    let corner = Vector3::new(-inset, top, far - NEWEL_SIZE / 2.0);
+
+    add_handrail(builder, settings, &[Vector3::new(-inset, top, start + NEWEL_SIZE / 2.0), corner], Vector3::unit_x());
+    add_handrail(builder, settings, &[corner, Vector3::new(inset, top, far - NEWEL_SIZE / 2.0)], Vector3::unit_z());
+  }
+}
+
+/// Wedge steps around a column on the Y axis, turning from +X towards +Z.
+fn add_spiral(builder: &mut MeshBuilder, settings: &StairSettings) {
+  let inner = settings.column_radius;
+  let outer = inner + settings.width;
+  let point = |radius: f32, angle: f32, height: f32| {
+    Vector3::new(radius * angle.cos(), height, radius * angle.sin())
+  };
+  let top = settings.steps as f32 * settings.rise;
+
+  builder.set_material(STAIR_STEP_MATERIAL);
+
+  for i in 0..settings.steps {
+    let (start, end) = (i as f32 * settings.turn.0, (i + 1) as f32 * settings.turn.0);
+    let height = (i + 1) as f32 * settings.rise;
+    let outline = |height: f32| [
+      point(inner, start, height),
+      point(outer, start, height),
+      point(outer, end, height),
+      point(inner, end, height),
+    ];
+
+    add_prism(builder, &outline(height - TREAD_THICKNESS), &outline(height));
+  }
+
+  builder.set_material(STAIR_FRAME_MATERIAL);
+
+  let column_top = top + if settings.handrails { settings.handrail_height } else { 0.0 };
+  let column = |height: f32| {
+    (0..COLUMN_SEGMENTS)
+      .map(|i| point(inner, i as f32 / COLUMN_SEGMENTS as f32 * std::f32::consts::TAU, height))
+      .collect::<Vec<_>>()
+  };
+
+  add_prism(builder, &column(0.0), &column(column_top));
+
+  // Stringer and handrail segments join the middles of neighbouring steps.
+  let middle = |i: u32| (i as f32 + 0.5) * settings.turn.0;
+  let radial = |angle: f32| Vector3::new(angle.cos(), 0.0, angle.sin());
+
+  if settings.stringers {
+    let radius = outer + settings.stringer_thickness / 2.0;
+    let drop = TREAD_THICKNESS + STRINGER_DEPTH / 2.0;
+
+    for i in 0..settings.steps.saturating_sub(1) {
+      let (a, b) = (middle(i), middle(i + 1));
+
+      builder.add_beam(
+        point(radius, a, (i + 1) as f32 * settings.rise - drop),
+        point(radius, b, (i + 2) as f32 * settings.rise - drop),
+        radial((a + b) / 2.0),
+        Vector2::new(settings.stringer_thickness, STRINGER_DEPTH),
+      );
+    }
+  }
+
+  if settings.handrails {
+    let radius = outer - NEWEL_SIZE / 2.0;
+
+    for i in 0..settings.steps {
+      let size = if i == 0 || i + 1 == settings.steps { NEWEL_SIZE } else { BALUSTER_SIZE };
+
+      Model::add_post(builder, point(radius, middle(i), (i + 1) as f32 * settings.rise), size, size, settings.handrail_height);
+    }
+
+    let rail = settings.handrail_height + HANDRAIL_SIZE / 2.0;
+
+    for i in 0..settings.steps.saturating_sub(1) {
+      let (a, b) = (middle(i), middle(i + 1));
+
+      builder.add_beam(
+        point(radius, a, (i + 1) as f32 * settings.rise + rail),
+        point(radius, b, (i + 2) as f32 * settings.rise + rail),
+        radial((a + b) / 2.0),
+        Vector2::new(HANDRAIL_SIZE, HANDRAIL_SIZE),
+      );
+    }
+  }
+}
+
+/// Solid between two copies of a convex outline, the second straight above
+/// the first.
+fn add_prism(builder: &mut MeshBuilder, bottom: &[Vector3<f32>], top: &[Vector3<f32>]) {
+  let center = bottom.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &point| sum + point) / bottom.len() as f32;
+
+  builder.add_polygon(bottom, -Vector3::unit_y());
+  builder.add_polygon(top, Vector3::unit_y());
+
+  for i in 0..bottom.len() {
+    let j = (i + 1) % bottom.len();
+    let outward = (bottom[i] + bottom[j]) / 2.0 - center;
+
+    builder.add_polygon(&[bottom[i], bottom[j], top[j], top[i]], outward);
+  }
+}
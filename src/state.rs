@@ -1,43 +1,69 @@
-use anyhow::Result;
-use cgmath::{
-  Deg,
-  InnerSpace,
-  Quaternion,
-  Rotation3,
-  Vector3,
-  Zero,
-};
+use anyhow::{bail, Result};
+use cgmath::{Point3, SquareMatrix, Transform as _, Vector3};
+use std::path::Path;
 use winit::{
-  event::{DeviceEvent, ElementState, KeyboardInput, VirtualKeyCode},
+  dpi::PhysicalPosition,
+  event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
   window::Window,
 };
-use wgpu::util::DeviceExt;
 
 use crate::{
-  camera::{CameraController, CameraRig, OrbitCamera, OrbitCameraController},
-  instance::Instance,
+  bounds::BoundingSphere,
+  camera::{Camera, CameraController, CameraRig, OrbitCamera, OrbitCameraController},
+  city::{self, CitySettings, CITY_MATERIALS},
+  color::ColorScheme,
+  erosion::{ErosionSettings, HydraulicSettings},
+  fence::{self, FenceSettings},
+  gizmo::{Gizmo, GizmoMode},
+  house::{self, HouseSettings},
+  lsystem::{LSystem, PlantPreset},
   model::{Model, ModelPrimitive},
+  modifier::{InstanceModifier, Jitter},
+  noise::{Noise, NoiseSettings},
+  ray::{self, Ray},
   render::Renderer,
+  scatter::{scatter, ScatterSettings},
+  scene::SceneGraph,
+  stairs::StairSettings,
+  streaming::{StreamSettings, TerrainStreamer},
+  transform::Transform,
+  voxel,
 };
 
-const NUM_INSTANCES_PER_ROW: u32 = 1;
+/// Cursor travel in pixels between press and release that still counts as
+/// a click rather than an orbit drag.
+const CLICK_TOLERANCE: f64 = 4.0;
+/// Gizmo arm length relative to the radius of the selected model.
+const GIZMO_SCALE: f32 = 1.25;
+const GIZMO_MIN_SIZE: f32 = 0.25;
+pub const HOUSE_POST_SIZE: f32 = 0.1;
+const SCATTER_POST_HEIGHT: f32 = 0.4;
+const SCATTER_POST_WIDTH: f32 = 0.05;
 
 pub struct State {
   camera_rig: CameraRig<OrbitCamera, OrbitCameraController>,
+  click_position: Option<PhysicalPosition<f64>>,
   config: wgpu::SurfaceConfiguration,
   cube_model: Model,
+  cursor_position: PhysicalPosition<f64>,
   device: wgpu::Device,
-  instance_buffer: wgpu::Buffer,
+  gizmo: Gizmo,
   mouse_pressed: bool,
   models: Vec<Model>,
   queue: wgpu::Queue,
   renderer: Renderer,
+  scene: SceneGraph,
+  seed: u64,
+  selected: Option<usize>,
   pub size: winit::dpi::PhysicalSize<u32>,
   surface: wgpu::Surface,
+  terrain: Option<TerrainStreamer>,
 }
 
 impl State {
-  pub async fn new(window: &Window) -> Self {
+  /// `seed` is handed to every procedural generator, so the same seed and
+  /// parameters always rebuild the same geometry.
+  pub async fn new(window: &Window, seed: u64) -> Self {
     let instance = wgpu::Instance::new(wgpu::Backends::all());
     let surface = unsafe { instance.create_surface(window) };
     let adapter = instance.request_adapter(
@@ -74,72 +100,297 @@ impl State {
     let res_dir = std::path::Path::new(env!("OUT_DIR")).join("res");
     let cube_model = Model::load(
       &device,
+      renderer.material_bind_group_layout(),
       res_dir.join("cube.obj"),
     ).unwrap();
 
-    let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
-      (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-        let position = Vector3 { x: x as f32, y: 0.0, z: z as f32 };
-        let rotation = if position.is_zero() {
-          Quaternion::from_axis_angle(
-            Vector3::unit_z(),
-            Deg(0.0),
-          )
-        } else {
-          Quaternion::from_axis_angle(position.normalize(), Deg(45.0))
-        };
-
-        Instance {
-          position,
-          rotation,
-        }
-      })
-    }).collect::<Vec<_>>();
-    let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-    let instance_buffer = device.create_buffer_init(
-      &wgpu::util::BufferInitDescriptor {
-        label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&instance_data),
-        usage: wgpu::BufferUsages::VERTEX,
-      }
-    );
+    let gizmo = Gizmo::new(&device, renderer.material_bind_group_layout());
 
     Self {
       camera_rig,
+      click_position: None,
       config,
       cube_model,
+      cursor_position: PhysicalPosition::new(0.0, 0.0),
       device,
-      instance_buffer,
+      gizmo,
       models: Vec::<Model>::new(),
       mouse_pressed: false,
       queue,
       renderer,
+      scene: SceneGraph::new(),
+      seed,
+      selected: None,
       size,
       surface,
+      terrain: None,
+    }
+  }
+
+  /// Adds a house with corner posts, grouped under one scene node named
+  /// "House", numbered from the second house on.
+  pub fn add_house(&mut self, settings: &HouseSettings) {
+    let layout = self.renderer.material_bind_group_layout();
+    let house = Model::house(&self.device, layout, settings);
+    let post = Model::post(&self.device, layout, HOUSE_POST_SIZE, HOUSE_POST_SIZE, settings.wall_height());
+    let house_index = self.models.len();
+    let name = (1..)
+      .map(|i| if i == 1 { String::from("House") } else { format!("House {}", i) })
+      .find(|name| self.scene.find(name).is_none())
+      .unwrap();
+    let root = self.scene.add_node(&name, Transform::default(), None, None);
+
+    self.models.push(house);
+    self.models.push(post);
+    self.scene.add_node(&format!("{} Walls", name), Transform::default(), Some(house_index), Some(root));
+
+    for (i, (x, z)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter().enumerate() {
+      let transform = Transform {
+        position: Vector3::new(x * settings.width / 2.0, 0.0, z * settings.length / 2.0),
+        ..Default::default()
+      };
+
+      self.scene.add_node(&format!("{} Post {}", name, i), transform, Some(house_index + 1), Some(root));
     }
   }
 
-  pub fn add_house(&mut self, width: f32, length: f32, height: f32) {
-    let model = Model::house(&self.device, width, length, height);
+  /// Adds the streets and blocks of a city as one model, and one model per
+  /// house variant with an instance on every lot it was given.
+  pub fn add_city(&mut self, settings: &CitySettings) {
+    let layout = self.renderer.material_bind_group_layout();
+    let city = city::generate(&CitySettings { seed: self.seed, ..*settings });
+
+    self.models.push(Model::from_builder(&self.device, layout, &city.ground, &CITY_MATERIALS));
+
+    for (house, instances) in city.houses.iter().zip(city.placements) {
+      if instances.is_empty() {
+        continue;
+      }
+
+      let mut model = Model::house(&self.device, layout, house);
+
+      model.set_instances(&self.device, &self.queue, instances);
+      self.models.push(model);
+    }
+  }
+
+  /// Adds a fence through the points in a JSON file, standing on the last
+  /// terrain added if there is one. See `fence::load_points`.
+  pub fn add_fence<P: AsRef<Path>>(&mut self, path: P, settings: &FenceSettings) -> Result<()> {
+    let points = fence::load_points(path)?;
+    let surface = self.models.iter()
+      .rev()
+      .find_map(|model| model.height_field.as_ref().map(|field| (field, model.transform.matrix())));
+    let model = Model::fence(&self.device, self.renderer.material_bind_group_layout(), &points, settings, surface);
 
     self.models.push(model);
+    Ok(())
+  }
+
+  /// Adds a building over the footprint in a JSON file. See
+  /// `house::load_footprint`.
+  pub fn add_footprint_house<P: AsRef<Path>>(&mut self, path: P, settings: &HouseSettings) -> Result<()> {
+    let footprint = house::load_footprint(path)?;
+    let model = Model::footprint_house(&self.device, self.renderer.material_bind_group_layout(), &footprint, settings);
+
+    self.models.push(model);
+    Ok(())
+  }
+
+  pub fn add_heightmap<P: AsRef<Path>>(
+    &mut self,
+    path: P,
+    scale: f32,
+    height_min: f32,
+    height_max: f32,
+    step: u32,
+  ) -> Result<()> {
+    let model = Model::heightmap(
+      &self.device,
+      self.renderer.material_bind_group_layout(),
+      path,
+      scale,
+      height_min,
+      height_max,
+      step,
+    )?;
+
+    self.models.push(model);
+    Ok(())
   }
 
   pub fn add_model_primitive(&mut self, primitive: ModelPrimitive, size: f32) {
-    let model = match primitive {
-      ModelPrimitive::Cube => Model::cube(&self.device, size),
-      ModelPrimitive::Plane => Model::plane(&self.device, size),
-    };
+    let model = Model::primitive(&self.device, self.renderer.material_bind_group_layout(), primitive, size);
+
+    self.models.push(model);
+  }
+
+  /// Adds a plant grown from a preset L-system, rewritten `iterations`
+  /// times when given instead of the preset's count.
+  pub fn add_plant(&mut self, preset: PlantPreset, iterations: Option<u32>) {
+    let mut system = LSystem::preset(preset);
+
+    if let Some(iterations) = iterations {
+      system.iterations = iterations;
+    }
+
+    let model = Model::plant(&self.device, self.renderer.material_bind_group_layout(), &system, self.seed);
+
+    self.models.push(model);
+  }
+
+  pub fn add_stairs(&mut self, settings: &StairSettings) {
+    let model = Model::stairs(&self.device, self.renderer.material_bind_group_layout(), settings);
 
     self.models.push(model);
   }
 
-  pub fn add_surface(&mut self, count: u32, size: f32, height_max: f32) {
-    let model = Model::surface(&self.device, count, size, height_max);
+  /// Adds an eroded noise surface. The seeds in `noise` and `erosion` are
+  /// replaced by the state's.
+  pub fn add_surface(
+    &mut self,
+    count: u32,
+    size: f32,
+    height_max: f32,
+    noise: NoiseSettings,
+    erosion: ErosionSettings,
+  ) {
+    let erosion = ErosionSettings {
+      hydraulic: erosion.hydraulic.map(|hydraulic| HydraulicSettings { seed: self.seed, ..hydraulic }),
+      ..erosion
+    };
+    let model = Model::surface(
+      &self.device,
+      self.renderer.material_bind_group_layout(),
+      count,
+      size,
+      height_max,
+      &Noise::new(NoiseSettings { seed: self.seed, ..noise }),
+      &erosion,
+    );
 
     self.models.push(model);
   }
 
+  /// Replaces the instances of the most recently added model with copies
+  /// from the modifier. A model placed by the scene graph keeps the
+  /// instances it gives it. The jitter is seeded with the state's seed.
+  pub fn apply_modifier(&mut self, modifier: &InstanceModifier, jitter: &Jitter) {
+    let index = match self.models.len().checked_sub(1) {
+      Some(index) if !self.scene.contains_model(index) => index,
+      _ => return,
+    };
+    let instances = modifier.instances(&Jitter { seed: self.seed, ..*jitter });
+
+    self.models[index].set_instances(&self.device, &self.queue, instances);
+  }
+
+  /// Recolors every model with the scheme, spread over the height of each
+  /// model and seeded with the state's seed.
+  pub fn color_models(&mut self, scheme: ColorScheme) {
+    for model in self.models.iter_mut() {
+      let generator = scheme.generator(model.aabb.min.y, model.aabb.max.y, self.seed);
+
+      model.apply_colors(&self.queue, &generator);
+    }
+  }
+
+  /// Removes the selected model from the scene.
+  pub fn delete_selected(&mut self) {
+    if let Some(index) = self.selected.take() {
+      self.gizmo.end_drag();
+      self.models.remove(index);
+      self.scene.remove_model(index);
+      self.renderer.set_selected(None);
+    }
+  }
+
+  /// Transform the gizmo drags for a model, with the pivot in its space. A
+  /// model placed by the scene drags the root of its group, so the rest of
+  /// the group moves with it.
+  fn drag_target(&self, index: usize) -> Option<(Transform, Point3<f32>)> {
+    let model = self.models.get(index)?;
+    let center = model.local_bounding_sphere().center;
+
+    match self.scene.model_root(index) {
+      Some(root) => {
+        let transform = self.scene.node(root).transform;
+
+        Some((transform, transform.matrix().invert()?.transform_point(center)))
+      }
+      None => Some((model.transform, center)),
+    }
+  }
+
+  /// Scatters copies of a post over the most recently added model, seeded
+  /// with the state's seed.
+  pub fn scatter_posts(&mut self, settings: &ScatterSettings) {
+    let instances = match self.models.last() {
+      Some(target) => scatter(target, &ScatterSettings { seed: self.seed, ..*settings }),
+      None => return,
+    };
+    let mut post = Model::post(
+      &self.device,
+      self.renderer.material_bind_group_layout(),
+      SCATTER_POST_WIDTH,
+      SCATTER_POST_WIDTH,
+      SCATTER_POST_HEIGHT,
+    );
+
+    post.set_instances(&self.device, &self.queue, instances);
+    self.models.push(post);
+  }
+
+  /// Exports the height, normal and slope maps of every terrain. The first
+  /// terrain is written with `prefix` and later ones with their model
+  /// index appended.
+  pub fn export_terrain(&self, prefix: &str) -> Result<()> {
+    let fields = self.models.iter()
+      .enumerate()
+      .filter_map(|(index, model)| model.height_field.as_ref().map(|field| (index, field)));
+
+    for (count, (index, field)) in fields.enumerate() {
+      if count == 0 {
+        field.export(prefix, self.seed)?;
+      } else {
+        field.export(format!("{}_{}", prefix, index), self.seed)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Points the orbit camera at the model with the given index so that it
+  /// fills the view. Does nothing if there is no such model.
+  pub fn frame_model(&mut self, index: usize) {
+    if let Some(model) = self.models.get(index) {
+      let fovy = self.renderer.fovy();
+
+      self.camera_rig.camera.frame(&model.world_bounding_sphere(), fovy);
+    }
+  }
+
+  /// Points the orbit camera at the bounds of every loaded model.
+  pub fn frame_scene(&mut self) {
+    if self.models.is_empty() {
+      return;
+    }
+
+    let sphere = BoundingSphere::enclosing(self.models.iter().map(Model::world_bounding_sphere));
+    let fovy = self.renderer.fovy();
+
+    self.camera_rig.camera.frame(&sphere, fovy);
+  }
+
+  /// Prints a validation report for the selected model.
+  pub fn inspect_selected(&self) {
+    if let Some(model) = self.selected_model() {
+      for report in model.validate() {
+        print!("{}", report);
+      }
+    }
+  }
+
   pub fn input(&mut self, event: &DeviceEvent) -> bool {
     match event {
       DeviceEvent::Key(
@@ -150,12 +401,33 @@ impl State {
         }
       ) => {
         match (*key, *state) {
+          (VirtualKeyCode::F, ElementState::Pressed) => {
+            self.frame_scene();
+          }
+          (key, ElementState::Pressed) if model_key_index(key).is_some() => {
+            self.frame_model(model_key_index(key).unwrap());
+          }
+          (VirtualKeyCode::Delete, ElementState::Pressed) => {
+            self.delete_selected();
+          }
+          (VirtualKeyCode::I, ElementState::Pressed) => {
+            self.inspect_selected();
+          }
           (VirtualKeyCode::L, ElementState::Pressed) => {
             self.renderer.toggle_light_render();
           }
           (VirtualKeyCode::R, ElementState::Pressed) => {
             self.renderer.toggle_light_rotation();
           }
+          (VirtualKeyCode::T, ElementState::Pressed) => {
+            self.gizmo.set_mode(GizmoMode::Translate);
+          }
+          (VirtualKeyCode::Y, ElementState::Pressed) => {
+            self.gizmo.set_mode(GizmoMode::Rotate);
+          }
+          (VirtualKeyCode::U, ElementState::Pressed) => {
+            self.gizmo.set_mode(GizmoMode::Scale);
+          }
           _ => {
             self.camera_rig.controller.process_keyboard(*key, *state);
           }
@@ -174,7 +446,7 @@ impl State {
         true
       }
       DeviceEvent::MouseMotion { delta } => {
-        if self.mouse_pressed {
+        if self.mouse_pressed && !self.gizmo.is_dragging() {
           self.camera_rig.controller.process_mouse(delta.0, delta.1);
         }
         true
@@ -183,9 +455,33 @@ impl State {
     }
   }
 
+  /// Selects the model under the given window position, or clears the
+  /// selection if there is none. The hit is logged at info level.
+  pub fn pick(&mut self, position: PhysicalPosition<f64>) {
+    let hit = self.screen_ray(position).and_then(|ray| ray::cast(&self.models, &ray));
+
+    if let Some(hit) = hit {
+      log::info!(
+        "Picked model {} instance {} mesh {} triangle {} at {:?}, normal {:?}, barycentrics {:?}",
+        hit.model,
+        hit.instance,
+        hit.mesh,
+        hit.triangle,
+        hit.position,
+        hit.normal,
+        hit.barycentrics,
+      );
+    }
+
+    self.selected = hit.map(|hit| hit.model);
+    self.renderer.set_selected(self.selected);
+  }
+
   pub fn prompt_for_file(&mut self) -> Result<()> {
     if let nfd::Response::Okay(path) = nfd::open_file_dialog(None, None)? {
-      self.models.push(Model::load(&self.device, path)?);
+      let layout = self.renderer.material_bind_group_layout();
+
+      self.models.push(Model::load(&self.device, layout, path)?);
     }
     Ok(())
   }
@@ -193,14 +489,16 @@ impl State {
   pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
     let output = self.surface.get_current_texture()?;
     let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let gizmo = &self.gizmo;
+    let overlay = self.selected.map(|_| gizmo.model());
 
     self.renderer.render(
       &self.device,
       &self.queue,
       &view,
       &self.cube_model,
-      &self.models,
-      &self.instance_buffer,
+      self.models.iter().chain(self.terrain.iter().flat_map(TerrainStreamer::models)),
+      overlay,
     );
 
     output.present();
@@ -218,9 +516,152 @@ impl State {
     }
   }
 
+  /// Ray from the camera through the given window position.
+  pub fn screen_ray(&self, position: PhysicalPosition<f64>) -> Option<Ray> {
+    Ray::from_screen(
+      self.renderer.view_projection(&self.camera_rig.camera),
+      position.x as f32,
+      position.y as f32,
+      self.size.width as f32,
+      self.size.height as f32,
+    )
+  }
+
+  pub fn selected_model(&self) -> Option<&Model> {
+    self.selected.and_then(|index| self.models.get(index))
+  }
+
+  /// Streams chunks of noise terrain around the camera from now on. The
+  /// seed in `noise` is replaced by the state's.
+  pub fn stream_terrain(&mut self, settings: StreamSettings, noise: NoiseSettings) {
+    self.terrain = Some(TerrainStreamer::new(settings, Noise::new(NoiseSettings { seed: self.seed, ..noise })));
+  }
+
   pub fn update(&mut self, dt: std::time::Duration) {
     self.camera_rig.controller.update_camera(&mut self.camera_rig.camera, dt);
     self.renderer.update_camera_uniform(&self.camera_rig.camera);
     self.renderer.update(&self.queue, dt);
+
+    if let Some(terrain) = self.terrain.as_mut() {
+      terrain.update(
+        &self.device,
+        &self.queue,
+        self.renderer.material_bind_group_layout(),
+        self.camera_rig.camera.get_position(),
+      );
+    }
+
+    if let Some(instances) = self.scene.take_changed_instances(self.models.len()) {
+      for (model, instances) in self.models.iter_mut().zip(instances) {
+        if let Some(instances) = instances {
+          model.set_instances(&self.device, &self.queue, instances);
+        }
+      }
+    }
+
+    if let Some(sphere) = self.selected_model().map(Model::world_bounding_sphere) {
+      self.gizmo.place(&self.queue, sphere.center, (sphere.radius * GIZMO_SCALE).max(GIZMO_MIN_SIZE));
+    }
+  }
+
+  /// Replaces the selected model, or every model but terrain when none is
+  /// selected, with blocks `voxel_size` wide filling the same space. The
+  /// transform and instances carry over.
+  pub fn voxelize_models(&mut self, voxel_size: f32) -> Result<()> {
+    if !voxel_size.is_finite() || voxel_size <= 0.0 {
+      bail!("voxel size must be a positive number, not {}", voxel_size);
+    }
+
+    let layout = self.renderer.material_bind_group_layout();
+    let selected = self.selected;
+
+    for (index, model) in self.models.iter_mut().enumerate() {
+      let chosen = match selected {
+        Some(selected) => index == selected,
+        None => model.height_field.is_none(),
+      };
+
+      if !chosen {
+        continue;
+      }
+
+      let mut blocks = Model::voxels(&self.device, layout, &voxel::voxelize(model, voxel_size), voxel_size);
+
+      blocks.set_transform(&self.queue, model.transform);
+      blocks.set_instances(&self.device, &self.queue, model.instances().to_vec());
+      *model = blocks;
+    }
+
+    Ok(())
+  }
+
+  pub fn window_input(&mut self, event: &WindowEvent) -> bool {
+    match event {
+      WindowEvent::CursorMoved { position, .. } => {
+        self.cursor_position = *position;
+
+        if self.gizmo.is_dragging() {
+          let transform = self.screen_ray(*position).and_then(|ray| self.gizmo.drag(&ray));
+
+          if let (Some(transform), Some(index)) = (transform, self.selected) {
+            match self.scene.model_root(index) {
+              Some(root) => self.scene.set_local_transform(root, transform),
+              None => self.models[index].set_transform(&self.queue, transform),
+            }
+          }
+        }
+        true
+      }
+      WindowEvent::MouseInput {
+        button: MouseButton::Left,
+        state: ElementState::Pressed,
+        ..
+      } => {
+        let ray = self.screen_ray(self.cursor_position);
+        let target = self.selected.and_then(|index| self.drag_target(index));
+
+        if let (Some(ray), Some((transform, pivot))) = (ray, target) {
+          if self.gizmo.begin_drag(&ray, transform, pivot) {
+            return true;
+          }
+        }
+
+        self.click_position = Some(self.cursor_position);
+        true
+      }
+      WindowEvent::MouseInput {
+        button: MouseButton::Left,
+        state: ElementState::Released,
+        ..
+      } => {
+        self.gizmo.end_drag();
+
+        if let Some(start) = self.click_position.take() {
+          let dx = self.cursor_position.x - start.x;
+          let dy = self.cursor_position.y - start.y;
+
+          if dx * dx + dy * dy <= CLICK_TOLERANCE * CLICK_TOLERANCE {
+            self.pick(self.cursor_position);
+          }
+        }
+        true
+      }
+      _ => false
+    }
+  }
+}
+
+fn model_key_index(key: VirtualKeyCode) -> Option<usize> {
+  match key {
+    VirtualKeyCode::Key1 => Some(0),
+    VirtualKeyCode::Key2 => Some(1),
+    VirtualKeyCode::Key3 => Some(2),
+    VirtualKeyCode::Key4 => Some(3),
+    VirtualKeyCode::Key5 => Some(4),
+    VirtualKeyCode::Key6 => Some(5),
+    VirtualKeyCode::Key7 => Some(6),
+    VirtualKeyCode::Key8 => Some(7),
+    VirtualKeyCode::Key9 => Some(8),
+    _ => None,
   }
 }
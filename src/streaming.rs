@@ -0,0 +1,322 @@
+use cgmath::{Point3, Vector3};
+use std::{
+  collections::HashMap,
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc,
+  },
+};
+
+use crate::{
+  mesh::{MeshBuilder, MeshData},
+  model::{self, Model},
+  noise::Noise,
+  terrain::HeightField,
+  transform::Transform,
+};
+
+/// Finished chunks turned into GPU buffers per frame, so a burst of
+/// arrivals does not stall one frame.
+const MAX_UPLOADS_PER_FRAME: usize = 8;
+const CHUNK_NAME: &str = "Terrain Chunk";
+/// Extra rings of chunks kept past the view distance, so chunks on the
+/// edge are not rebuilt every time the camera crosses back and forth.
+const EVICTION_MARGIN: i32 = 1;
+
+pub type ChunkCoord = (i32, i32);
+
+#[derive(Copy, Clone, Debug)]
+pub struct StreamSettings {
+  /// Width of a chunk in world units.
+  pub chunk_size: f32,
+  /// Quads along each side of a chunk at full detail. Each level of detail
+  /// halves it, so it should be a power of two.
+  pub resolution: u32,
+  pub height_max: f32,
+  /// Radius in chunks of the area kept loaded around the camera.
+  pub view_distance: u32,
+  /// Rings of chunks between one level of detail and the next.
+  pub lod_distance: u32,
+  pub max_lod: u32,
+}
+
+impl Default for StreamSettings {
+  fn default() -> Self {
+    Self {
+      chunk_size: 32.0,
+      resolution: 32,
+      height_max: 8.0,
+      view_distance: 8,
+      lod_distance: 2,
+      max_lod: 4,
+    }
+  }
+}
+
+/// Level of detail of a chunk and of its neighbours on the -X, +X, -Z and
+/// +Z sides. Edges shared with a coarser neighbour follow its resolution so
+/// no cracks open between them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ChunkDetail {
+  lod: u32,
+  neighbours: [u32; 4],
+}
+
+/// Chunk the streamer receives from the rayon pool, with its meshes and
+/// their BVHs already built so only the buffers are made on upload.
+type ChunkResult = (ChunkCoord, ChunkDetail, HeightField, Vec<MeshData>);
+
+struct Chunk {
+  detail: Option<ChunkDetail>,
+  model: Option<Model>,
+  pending: Option<ChunkDetail>,
+}
+
+/// Terrain split into square chunks that are generated on the rayon pool
+/// as the camera moves. Distant chunks use fewer quads and chunks that
+/// fall out of range are dropped along with their GPU buffers.
+pub struct TerrainStreamer {
+  settings: StreamSettings,
+  noise: Arc<Noise>,
+  center: Option<ChunkCoord>,
+  chunks: HashMap<ChunkCoord, Chunk>,
+  sender: Sender<ChunkResult>,
+  receiver: Receiver<ChunkResult>,
+}
+
+impl TerrainStreamer {
+  pub fn new(settings: StreamSettings, noise: Noise) -> Self {
+    let (sender, receiver) = mpsc::channel();
+
+    Self {
+      settings,
+      noise: Arc::new(noise),
+      center: None,
+      chunks: HashMap::new(),
+      sender,
+      receiver,
+    }
+  }
+
+  pub fn models(&self) -> impl Iterator<Item = &Model> {
+    self.chunks.values().filter_map(|chunk| chunk.model.as_ref())
+  }
+
+  /// Uploads chunks that finished generating and, when the camera has moved
+  /// into another chunk, requests the chunks now in range and evicts the
+  /// ones that are not.
+  pub fn update(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    position: Point3<f32>,
+  ) {
+    let center = (
+      (position.x / self.settings.chunk_size).floor() as i32,
+      (position.z / self.settings.chunk_size).floor() as i32,
+    );
+
+    if self.center != Some(center) {
+      self.center = Some(center);
+      self.request_chunks(center);
+      self.evict_chunks(center);
+    }
+
+    for (coord, detail, field, meshes) in self.receiver.try_iter().take(MAX_UPLOADS_PER_FRAME) {
+      let chunk = match self.chunks.get_mut(&coord) {
+        Some(chunk) if chunk.pending == Some(detail) => chunk,
+        // Evicted or asked for at another detail since the job started.
+        _ => continue,
+      };
+      let mut model = Model::terrain_from_data(device, layout, CHUNK_NAME, field, meshes);
+
+      model.set_transform(queue, Transform {
+        position: chunk_center(coord, self.settings.chunk_size),
+        ..Default::default()
+      });
+      chunk.detail = Some(detail);
+      chunk.model = Some(model);
+      chunk.pending = None;
+    }
+  }
+
+  fn evict_chunks(&mut self, center: ChunkCoord) {
+    let limit = self.settings.view_distance as i32 + EVICTION_MARGIN;
+
+    self.chunks.retain(|&coord, _| ring(center, coord) <= limit);
+  }
+
+  fn lod(&self, center: ChunkCoord, coord: ChunkCoord) -> u32 {
+    (ring(center, coord) as u32 / self.settings.lod_distance.max(1)).min(self.settings.max_lod)
+  }
+
+  fn request_chunks(&mut self, center: ChunkCoord) {
+    let radius = self.settings.view_distance as i32;
+
+    for z in center.1 - radius..=center.1 + radius {
+      for x in center.0 - radius..=center.0 + radius {
+        let coord = (x, z);
+        let detail = ChunkDetail {
+          lod: self.lod(center, coord),
+          neighbours: [
+            self.lod(center, (x - 1, z)),
+            self.lod(center, (x + 1, z)),
+            self.lod(center, (x, z - 1)),
+            self.lod(center, (x, z + 1)),
+          ],
+        };
+        let chunk = self.chunks.entry(coord).or_insert(Chunk {
+          detail: None,
+          model: None,
+          pending: None,
+        });
+
+        if chunk.detail == Some(detail) || chunk.pending == Some(detail) {
+          continue;
+        }
+
+        let noise = self.noise.clone();
+        let sender = self.sender.clone();
+        let settings = self.settings;
+
+        chunk.pending = Some(detail);
+        rayon::spawn(move || {
+          let (field, normals) = generate_chunk(&noise, &settings, coord, detail);
+          let mut builder = MeshBuilder::new(CHUNK_NAME);
+
+          field.add_with_normals(&mut builder, &normals);
+          model::color_terrain(&mut builder, 0.0, settings.height_max);
+
+          let meshes = builder.build();
+
+          // The streamer may have been dropped while the job ran.
+          let _ = sender.send((coord, detail, field, meshes));
+        });
+      }
+    }
+  }
+}
+
+fn chunk_center(coord: ChunkCoord, chunk_size: f32) -> Vector3<f32> {
+  Vector3::new((coord.0 as f32 + 0.5) * chunk_size, 0.0, (coord.1 as f32 + 0.5) * chunk_size)
+}
+
+/// Heights of one chunk, centered on the origin, and their normals. Points
+/// on an edge shared with a coarser neighbour are moved onto the line
+/// between the points the neighbour has there. The chunk is generated with
+/// an extra ring of points around it, so normals on its edges take in the
+/// chunks next to it and match theirs.
+fn generate_chunk(
+  noise: &Noise,
+  settings: &StreamSettings,
+  coord: ChunkCoord,
+  detail: ChunkDetail,
+) -> (HeightField, Vec<Vector3<f32>>) {
+  let quads = resolution(settings, detail.lod);
+  let spacing = settings.chunk_size / quads as f32;
+  let corner = (coord.0 as f32 * settings.chunk_size, coord.1 as f32 * settings.chunk_size);
+  let sample = |column: u32, row: u32| {
+    noise.sample(corner.0 + column as f32 * spacing, corner.1 + row as f32 * spacing) * settings.height_max
+  };
+  // One point wider on every side, so point (column, row) of the chunk is
+  // (column + 1, row + 1) here.
+  let mut padded = HeightField {
+    columns: quads + 3,
+    rows: quads + 3,
+    spacing,
+    heights: Vec::with_capacity(((quads + 3) * (quads + 3)) as usize),
+  };
+
+  for row in 0..quads + 3 {
+    for column in 0..quads + 3 {
+      let x = corner.0 + (column as f32 - 1.0) * spacing;
+      let z = corner.1 + (row as f32 - 1.0) * spacing;
+
+      padded.heights.push(noise.sample(x, z) * settings.height_max);
+    }
+  }
+
+  for (side, &neighbour_lod) in detail.neighbours.iter().enumerate() {
+    let step = quads / resolution(settings, neighbour_lod).min(quads);
+
+    if step <= 1 {
+      continue;
+    }
+
+    for i in 0..=quads {
+      let offset = i % step;
+
+      if offset == 0 {
+        continue;
+      }
+
+      let (before, after) = (i - offset, i - offset + step);
+      let t = offset as f32 / step as f32;
+      let point = |i: u32| match side {
+        0 => (0, i),
+        1 => (quads, i),
+        2 => (i, 0),
+        _ => (i, quads),
+      };
+      let (column, row) = point(i);
+      let (c0, r0) = point(before);
+      let (c1, r1) = point(after);
+
+      padded.heights[((row + 1) * (quads + 3) + column + 1) as usize] = sample(c0, r0) * (1.0 - t) + sample(c1, r1) * t;
+    }
+  }
+
+  let mut field = HeightField {
+    columns: quads + 1,
+    rows: quads + 1,
+    spacing,
+    heights: Vec::with_capacity(((quads + 1) * (quads + 1)) as usize),
+  };
+  let mut normals = Vec::with_capacity(field.heights.capacity());
+
+  for row in 1..=quads + 1 {
+    for column in 1..=quads + 1 {
+      field.heights.push(padded.height(column, row));
+      normals.push(padded.normal(column, row));
+    }
+  }
+
+  (field, normals)
+}
+
+fn resolution(settings: &StreamSettings, lod: u32) -> u32 {
+  (settings.resolution >> lod).max(1)
+}
+
+/// Chebyshev distance in chunks, so the loaded area and the detail rings
+/// are squares around the camera.
+fn ring(center: ChunkCoord, coord: ChunkCoord) -> i32 {
+  (coord.0 - center.0).abs().max((coord.1 - center.1).abs())
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::InnerSpace;
+
+  use super::*;
+  use crate::noise::NoiseSettings;
+
+  #[test]
+  fn neighbouring_chunks_share_edge_normals() {
+    let noise = Noise::new(NoiseSettings { seed: 7, ..Default::default() });
+    let settings = StreamSettings { resolution: 8, ..Default::default() };
+    let detail = ChunkDetail { lod: 0, neighbours: [0; 4] };
+    let (_, left) = generate_chunk(&noise, &settings, (0, 0), detail);
+    let (_, right) = generate_chunk(&noise, &settings, (1, 0), detail);
+    let (_, front) = generate_chunk(&noise, &settings, (0, 1), detail);
+    let points = settings.resolution + 1;
+
+    for i in 0..points {
+      let edge = (i * points + points - 1) as usize;
+
+      assert!((left[edge] - right[(i * points) as usize]).magnitude() < 1e-4);
+      assert!((left[((points - 1) * points + i) as usize] - front[i as usize]).magnitude() < 1e-4);
+    }
+  }
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Result};
+use cgmath::{InnerSpace, Vector3};
+use image::{GrayImage, ImageBuffer, Luma, RgbImage};
+use std::{fs, path::Path};
+
+use crate::{
+  mesh::MeshBuilder,
+  noise::Noise,
+};
+
+/// Grid of heights centered on the origin, `spacing` apart on X and Z.
+/// Rows run along +Z and columns along +X.
+pub struct HeightField {
+  pub columns: u32,
+  pub rows: u32,
+  pub spacing: f32,
+  pub heights: Vec<f32>,
+}
+
+impl HeightField {
+  /// Square field `2 * (count / 2) + 1` points wide, raised by the noise
+  /// up to `height_max`.
+  pub fn from_noise(noise: &Noise, count: u32, spacing: f32, height_max: f32) -> Self {
+    let size = count / 2 * 2 + 1;
+    let mut field = Self {
+      columns: size,
+      rows: size,
+      spacing,
+      heights: Vec::with_capacity((size * size) as usize),
+    };
+
+    for row in 0..size {
+      for column in 0..size {
+        let position = field.position(column, row, 0.0);
+
+        field.heights.push(noise.sample(position.x, position.z) * height_max);
+      }
+    }
+
+    field
+  }
+
+  /// Field from a grayscale image, 8 or 16 bits per channel. Each point
+  /// averages a `step` by `step` block of pixels, the points are
+  /// `scale * step` apart and black to white spans `height_min` to
+  /// `height_max`.
+  pub fn from_image<P: AsRef<Path>>(
+    path: P,
+    scale: f32,
+    height_min: f32,
+    height_max: f32,
+    step: u32,
+  ) -> Result<Self> {
+    let image = image::open(path.as_ref())?.into_luma16();
+    let step = step.max(1);
+    let columns = image.width().div_ceil(step);
+    let rows = image.height().div_ceil(step);
+
+    if columns < 2 || rows < 2 {
+      bail!("{} is too small to make a terrain", path.as_ref().display());
+    }
+
+    let mut heights = Vec::with_capacity((columns * rows) as usize);
+
+    for row in 0..rows {
+      for column in 0..columns {
+        let x0 = column * step;
+        let y0 = row * step;
+        let x1 = (x0 + step).min(image.width());
+        let y1 = (y0 + step).min(image.height());
+        let sum = (y0..y1)
+          .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+          .map(|(x, y)| image.get_pixel(x, y)[0] as f32)
+          .sum::<f32>();
+        let value = sum / ((x1 - x0) * (y1 - y0)) as f32 / u16::MAX as f32;
+
+        heights.push(height_min + (height_max - height_min) * value);
+      }
+    }
+
+    Ok(Self {
+      columns,
+      rows,
+      spacing: scale * step as f32,
+      heights,
+    })
+  }
+
+  /// Adds the field to the builder with the same layout as the quad grid of
+  /// `MeshBuilder::add_linked_quad`, with the normal of the field at every
+  /// vertex.
+  pub fn add_to(&self, builder: &mut MeshBuilder) {
+    self.add_with_normals(builder, &self.normals());
+  }
+
+  /// Like `add_to` with one normal per point in row order, for a field cut
+  /// out of a larger surface whose edges its own normals would flatten.
+  pub fn add_with_normals(&self, builder: &mut MeshBuilder, normals: &[Vector3<f32>]) {
+    for row in 0..self.rows {
+      for column in 0..self.columns {
+        let position = self.position(column, row, self.height(column, row));
+        let normal = normals[(row * self.columns + column) as usize];
+
+        builder.add_linked_quad(position, normal, row > 0 && column > 0, self.columns);
+      }
+    }
+  }
+
+  /// Writes `<prefix>_height.png` as 16-bit grayscale spanning the lowest
+  /// to the highest point, `<prefix>_normal.png` as a tangent-space normal
+  /// map with green pointing to -Z, `<prefix>_slope.png` with black flat
+  /// and white vertical, and `<prefix>.txt` with the height range, spacing
+  /// and seed needed to rebuild the terrain.
+  pub fn export<P: AsRef<Path>>(&self, prefix: P, seed: u64) -> Result<()> {
+    let prefix = prefix.as_ref().display().to_string();
+    let (low, high) = self.range();
+    let range = if high > low { high - low } else { 1.0 };
+    let height_map: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_fn(self.columns, self.rows, |x, y| {
+      Luma([((self.height(x, y) - low) / range * u16::MAX as f32).round() as u16])
+    });
+    let normal_map = RgbImage::from_fn(self.columns, self.rows, |x, y| {
+      let normal = self.normal(x, y);
+
+      image::Rgb([encode_unit(normal.x), encode_unit(-normal.z), encode_unit(normal.y)])
+    });
+    let slope_map = GrayImage::from_fn(self.columns, self.rows, |x, y| {
+      let slope = self.normal(x, y).y.clamp(-1.0, 1.0).acos() / std::f32::consts::FRAC_PI_2;
+
+      Luma([(slope.min(1.0) * u8::MAX as f32).round() as u8])
+    });
+
+    height_map.save(format!("{}_height.png", prefix))?;
+    normal_map.save(format!("{}_normal.png", prefix))?;
+    slope_map.save(format!("{}_slope.png", prefix))?;
+    fs::write(
+      format!("{}.txt", prefix),
+      format!(
+        "seed = {}\ncolumns = {}\nrows = {}\nspacing = {}\nheight_min = {}\nheight_max = {}\n",
+        seed, self.columns, self.rows, self.spacing, low, high,
+      ),
+    )?;
+
+    Ok(())
+  }
+
+  pub fn height(&self, column: u32, row: u32) -> f32 {
+    self.heights[(row * self.columns + column) as usize]
+  }
+
+  /// Surface normal from central differences, one-sided at the edges.
+  pub fn normal(&self, column: u32, row: u32) -> Vector3<f32> {
+    let left = column.saturating_sub(1);
+    let right = (column + 1).min(self.columns - 1);
+    let back = row.saturating_sub(1);
+    let front = (row + 1).min(self.rows - 1);
+    let dx = (self.height(right, row) - self.height(left, row)) / ((right - left).max(1) as f32 * self.spacing);
+    let dz = (self.height(column, front) - self.height(column, back)) / ((front - back).max(1) as f32 * self.spacing);
+
+    Vector3::new(-dx, 1.0, -dz).normalize()
+  }
+
+  /// Normal of every point in row order.
+  pub fn normals(&self) -> Vec<Vector3<f32>> {
+    (0..self.rows).flat_map(|row| (0..self.columns).map(move |column| self.normal(column, row))).collect()
+  }
+
+  pub fn position(&self, column: u32, row: u32, height: f32) -> Vector3<f32> {
+    Vector3::new(
+      (column as f32 - (self.columns - 1) as f32 / 2.0) * self.spacing,
+      height,
+      (row as f32 - (self.rows - 1) as f32 / 2.0) * self.spacing,
+    )
+  }
+
+  /// Lowest and highest point of the field.
+  pub fn range(&self) -> (f32, f32) {
+    self.heights.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &height| {
+      (low.min(height), high.max(height))
+    })
+  }
+
+  /// Height between the grid points under a position on the XZ plane, or
+  /// `None` when the position is off the field.
+  pub fn sample(&self, x: f32, z: f32) -> Option<f32> {
+    if self.columns == 0 || self.rows == 0 {
+      return None;
+    }
+
+    let column = x / self.spacing + (self.columns - 1) as f32 / 2.0;
+    let row = z / self.spacing + (self.rows - 1) as f32 / 2.0;
+
+    if column < 0.0 || row < 0.0 || column > (self.columns - 1) as f32 || row > (self.rows - 1) as f32 {
+      return None;
+    }
+
+    // A field one point wide has no cell to blend across, so both corners
+    // of the cell are that point.
+    let (c0, r0) = ((column as u32).min(self.columns.saturating_sub(2)), (row as u32).min(self.rows.saturating_sub(2)));
+    let (c1, r1) = ((c0 + 1).min(self.columns - 1), (r0 + 1).min(self.rows - 1));
+    let (u, v) = (column - c0 as f32, row - r0 as f32);
+    let near = self.height(c0, r0) * (1.0 - u) + self.height(c1, r0) * u;
+    let far = self.height(c0, r1) * (1.0 - u) + self.height(c1, r1) * u;
+
+    Some(near * (1.0 - v) + far * v)
+  }
+}
+
+fn encode_unit(value: f32) -> u8 {
+  ((value * 0.5 + 0.5).clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    erosion::{ErosionSettings, HydraulicSettings},
+    noise::NoiseSettings,
+    testing::mesh_bytes,
+  };
+
+  fn eroded(seed: u64) -> MeshBuilder {
+    let noise = Noise::new(NoiseSettings { seed, ..Default::default() });
+    let mut field = HeightField::from_noise(&noise, 16, 8.0, 2.0);
+    let mut builder = MeshBuilder::new("Terrain");
+
+    ErosionSettings {
+      hydraulic: Some(HydraulicSettings { iterations: 200, seed, ..Default::default() }),
+      thermal: None,
+    }.apply(&mut field);
+    field.add_to(&mut builder);
+    builder
+  }
+
+  #[test]
+  fn samples_between_grid_points() {
+    let field = HeightField::from_noise(&Noise::new(Default::default()), 4, 1.0, 1.0);
+    let expected = (field.height(2, 2) + field.height(3, 2)) / 2.0;
+
+    assert!((field.sample(0.5, 0.0).unwrap() - expected).abs() < 1e-6);
+    assert_eq!(field.sample(10.0, 0.0), None);
+  }
+
+  #[test]
+  fn samples_a_single_point() {
+    let field = HeightField::from_noise(&Noise::new(Default::default()), 0, 1.0, 1.0);
+
+    assert_eq!(field.sample(0.0, 0.0), Some(field.height(0, 0)));
+    assert_eq!(field.sample(0.5, 0.0), None);
+  }
+
+  #[test]
+  fn terrain_is_the_same_for_a_seed() {
+    assert_eq!(mesh_bytes(&eroded(7)), mesh_bytes(&eroded(7)));
+    assert_ne!(mesh_bytes(&eroded(7)), mesh_bytes(&eroded(8)));
+  }
+}
@@ -0,0 +1,23 @@
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::{instance::Instance, mesh::MeshBuilder};
+
+/// Everything a builder would upload, as raw bytes, so generated meshes can
+/// be compared exactly.
+pub fn mesh_bytes(builder: &MeshBuilder) -> Vec<u8> {
+  let mut bytes = Vec::new();
+
+  for mesh in builder.build() {
+    bytes.extend_from_slice(bytemuck::cast_slice(&mesh.vertices));
+    bytes.extend_from_slice(bytemuck::cast_slice(&mesh.indices));
+  }
+
+  bytes
+}
+
+/// Instances as they would be uploaded, as raw bytes.
+pub fn instance_bytes(instances: &[Instance]) -> Vec<u8> {
+  let raw = instances.iter().map(|instance| instance.to_raw(Matrix4::identity())).collect::<Vec<_>>();
+
+  bytemuck::cast_slice(&raw).to_vec()
+}
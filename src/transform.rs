@@ -0,0 +1,68 @@
+use cgmath::{
+  ElementWise,
+  EuclideanSpace,
+  Matrix,
+  Matrix3,
+  Matrix4,
+  One,
+  Point3,
+  Quaternion,
+  SquareMatrix,
+  Vector3,
+};
+
+/// Placement of a whole model in the world. Instances of the model are
+/// positioned relative to it.
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+  pub position: Vector3<f32>,
+  pub rotation: Quaternion<f32>,
+  pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self {
+      position: Vector3::new(0.0, 0.0, 0.0),
+      rotation: Quaternion::one(),
+      scale: Vector3::new(1.0, 1.0, 1.0),
+    }
+  }
+}
+
+impl Transform {
+  pub fn matrix(&self) -> Matrix4<f32> {
+    Matrix4::from_translation(self.position)
+      * Matrix4::from(self.rotation)
+      * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+  }
+
+  /// Transform of `child` placed in the space of this one. Scale combines
+  /// per axis, which is exact unless a rotated child sits under a
+  /// non-uniformly scaled parent.
+  pub fn then(&self, child: &Transform) -> Transform {
+    Transform {
+      position: self.transform_point(Point3::from_vec(child.position)).to_vec(),
+      rotation: self.rotation * child.rotation,
+      scale: self.scale.mul_element_wise(child.scale),
+    }
+  }
+
+  pub fn transform_point(&self, point: Point3<f32>) -> Point3<f32> {
+    let scaled = point.to_vec().mul_element_wise(self.scale);
+
+    Point3::from_vec(self.rotation * scaled + self.position)
+  }
+}
+
+/// Inverse transpose of the upper 3x3 of the matrix, which keeps normals
+/// perpendicular to surfaces under non-uniform scale.
+pub fn normal_matrix(matrix: Matrix4<f32>) -> Matrix3<f32> {
+  let linear = Matrix3::from_cols(
+    matrix.x.truncate(),
+    matrix.y.truncate(),
+    matrix.z.truncate(),
+  );
+
+  linear.invert().map_or(Matrix3::identity(), |inverse| inverse.transpose())
+}
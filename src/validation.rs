@@ -0,0 +1,253 @@
+use cgmath::{InnerSpace, Vector3};
+use std::{
+  collections::HashMap,
+  fmt,
+};
+
+use crate::mesh::{MeshVertex, DEGENERATE_AREA};
+
+const NORMAL_EPSILON: f32 = 1e-6;
+const WELD_SCALE: f32 = 1e5;
+
+/// Problems found in a triangle list. Triangle entries are face indices
+/// (index into `indices` divided by three) and vertex entries are indices
+/// into the vertex list.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+  pub name: String,
+  pub num_vertices: usize,
+  pub num_triangles: usize,
+  pub trailing_indices: usize,
+  pub degenerate_triangles: Vec<usize>,
+  pub non_finite_vertices: Vec<usize>,
+  pub unused_vertices: Vec<usize>,
+  pub out_of_range_indices: Vec<usize>,
+  pub non_manifold_edges: usize,
+  /// Edges of only one triangle. Open surfaces like terrain have them, so
+  /// they are reported without making the mesh invalid.
+  pub boundary_edges: usize,
+  pub inconsistent_winding_edges: usize,
+  pub zero_length_normals: Vec<usize>,
+}
+
+impl ValidationReport {
+  pub fn is_valid(&self) -> bool {
+    self.trailing_indices == 0
+      && self.degenerate_triangles.is_empty()
+      && self.non_finite_vertices.is_empty()
+      && self.unused_vertices.is_empty()
+      && self.out_of_range_indices.is_empty()
+      && self.non_manifold_edges == 0
+      && self.inconsistent_winding_edges == 0
+      && self.zero_length_normals.is_empty()
+  }
+}
+
+impl fmt::Display for ValidationReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "{}: {} vertices, {} triangles{}",
+      self.name,
+      self.num_vertices,
+      self.num_triangles,
+      if self.is_valid() { ", no problems found" } else { "" },
+    )?;
+
+    let counts = [
+      ("trailing indices", self.trailing_indices),
+      ("degenerate triangles", self.degenerate_triangles.len()),
+      ("non-finite positions", self.non_finite_vertices.len()),
+      ("unused vertices", self.unused_vertices.len()),
+      ("out of range indices", self.out_of_range_indices.len()),
+      ("non-manifold edges", self.non_manifold_edges),
+      ("inconsistently wound edges", self.inconsistent_winding_edges),
+      ("zero length normals", self.zero_length_normals.len()),
+    ];
+
+    for (label, count) in counts.iter().filter(|(_, count)| *count > 0) {
+      writeln!(f, "  {}: {}", label, count)?;
+    }
+
+    if self.boundary_edges > 0 {
+      writeln!(f, "  open boundary edges: {} (fine for open surfaces)", self.boundary_edges)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Checks a triangle list for common construction mistakes. Edges are
+/// compared by vertex position rather than index so that meshes built from
+/// unshared quads, like those from `MeshBuilder::add_quad`, are treated as
+/// connected surfaces.
+pub fn validate(name: &str, vertices: &[MeshVertex], indices: &[u32]) -> ValidationReport {
+  let mut report = ValidationReport {
+    name: String::from(name),
+    num_vertices: vertices.len(),
+    num_triangles: indices.len() / 3,
+    trailing_indices: indices.len() % 3,
+    ..Default::default()
+  };
+  let mut used = vec![false; vertices.len()];
+
+  for (i, vertex) in vertices.iter().enumerate() {
+    if vertex.position.iter().any(|p| !p.is_finite()) {
+      report.non_finite_vertices.push(i);
+    }
+    if Vector3::from(vertex.normal).magnitude2() < NORMAL_EPSILON {
+      report.zero_length_normals.push(i);
+    }
+  }
+
+  for (i, &index) in indices.iter().enumerate() {
+    match used.get_mut(index as usize) {
+      Some(used) => *used = true,
+      None => report.out_of_range_indices.push(i),
+    }
+  }
+
+  report.unused_vertices = used.iter()
+    .enumerate()
+    .filter(|(_, used)| !**used)
+    .map(|(i, _)| i)
+    .collect();
+
+  let mut welded = HashMap::new();
+  let mut edges: HashMap<(usize, usize), (u32, i32)> = HashMap::new();
+
+  for (face, triangle) in indices.chunks_exact(3).enumerate() {
+    if triangle.iter().any(|&i| i as usize >= vertices.len()) {
+      continue;
+    }
+
+    let positions = [
+      Vector3::from(vertices[triangle[0] as usize].position),
+      Vector3::from(vertices[triangle[1] as usize].position),
+      Vector3::from(vertices[triangle[2] as usize].position),
+    ];
+    let area = (positions[1] - positions[0]).cross(positions[2] - positions[0]).magnitude();
+
+    if area <= DEGENERATE_AREA || !area.is_finite() {
+      report.degenerate_triangles.push(face);
+      continue;
+    }
+
+    let keys = positions.map(|position| {
+      let next = welded.len();
+
+      *welded.entry(weld_key(position)).or_insert(next)
+    });
+
+    for k in 0..3 {
+      let (a, b) = (keys[k], keys[(k + 1) % 3]);
+      let (edge, direction) = if a < b { ((a, b), 1) } else { ((b, a), -1) };
+      let entry = edges.entry(edge).or_insert((0, 0));
+
+      entry.0 += 1;
+      entry.1 += direction;
+    }
+  }
+
+  for (count, direction_sum) in edges.values() {
+    match count {
+      1 => report.boundary_edges += 1,
+      2 if *direction_sum != 0 => report.inconsistent_winding_edges += 1,
+      2 => {}
+      _ => report.non_manifold_edges += 1,
+    }
+  }
+
+  report
+}
+
+/// Rounds a position so that points closer than the weld distance match.
+pub fn weld_key(position: Vector3<f32>) -> (i64, i64, i64) {
+  (
+    (position.x * WELD_SCALE).round() as i64,
+    (position.y * WELD_SCALE).round() as i64,
+    (position.z * WELD_SCALE).round() as i64,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Vector3;
+
+  use super::*;
+  use crate::{
+    house::{self, HouseSettings, RoofStyle},
+    mesh::{MeshBuilder, COLOR},
+    model::ModelPrimitive,
+    noise::Noise,
+    stairs::{self, StairSettings},
+    terrain::HeightField,
+  };
+
+  fn vertex(position: [f32; 3]) -> MeshVertex {
+    MeshVertex { position, normal: [0.0, 1.0, 0.0], color: COLOR }
+  }
+
+  #[test]
+  fn closed_box_is_valid() {
+    let mut builder = MeshBuilder::new("Box");
+
+    builder.add_box(Vector3::new(-1.0, 0.0, 2.0), Vector3::new(1.0, 2.0, 3.0));
+
+    let report = builder.validate();
+
+    assert!(report.is_valid(), "{}", report);
+    assert_eq!(report.boundary_edges, 0);
+  }
+
+  #[test]
+  fn open_surfaces_are_valid() {
+    let plane = ModelPrimitive::Plane.build(2.0).validate();
+
+    assert!(plane.is_valid(), "{}", plane);
+    assert_eq!(plane.boundary_edges, 4);
+
+    let field = HeightField::from_noise(&Noise::new(Default::default()), 8, 2.0, 1.0);
+    let mut builder = MeshBuilder::new("Terrain");
+
+    field.add_to(&mut builder);
+
+    let terrain = builder.validate();
+
+    assert!(terrain.is_valid(), "{}", terrain);
+    assert_eq!(terrain.boundary_edges, 4 * 8);
+  }
+
+  #[test]
+  fn generated_meshes_have_no_broken_triangles() {
+    let stairs = stairs::build(&StairSettings::default()).validate();
+
+    assert!(stairs.is_valid(), "{}", stairs);
+
+    for roof in [RoofStyle::Gable, RoofStyle::Hip, RoofStyle::Flat, RoofStyle::Shed, RoofStyle::Mansard] {
+      let house = house::build(&HouseSettings { roof, ..Default::default() }).validate();
+
+      assert!(house.is_valid(), "{:?} roof: {}", roof, house);
+    }
+  }
+
+  #[test]
+  fn finds_broken_triangles() {
+    let vertices = [
+      vertex([0.0, 0.0, 0.0]),
+      vertex([1.0, 0.0, 0.0]),
+      vertex([0.0, 0.0, 1.0]),
+      vertex([2.0, 0.0, 0.0]),
+      vertex([f32::NAN, 0.0, 0.0]),
+      vertex([5.0, 5.0, 5.0]),
+    ];
+    let report = validate("Broken", &vertices, &[0, 2, 1, 0, 1, 3, 0, 1, 4, 0, 9, 2, 1]);
+
+    assert!(!report.is_valid());
+    assert_eq!(report.trailing_indices, 1);
+    assert_eq!(report.degenerate_triangles, vec![1, 2]);
+    assert_eq!(report.non_finite_vertices, vec![4]);
+    assert_eq!(report.unused_vertices, vec![5]);
+    assert_eq!(report.out_of_range_indices, vec![10]);
+  }
+}
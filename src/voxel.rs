@@ -0,0 +1,452 @@
+use cgmath::{InnerSpace, Vector3};
+use std::collections::HashMap;
+
+use crate::{
+  material::{Material, DEFAULT_TINT},
+  mesh::MeshBuilder,
+  model::Model,
+};
+
+pub const VOXEL_MATERIALS: [(&str, [f32;4]); 1] = [("Voxel", DEFAULT_TINT)];
+
+/// Cross products shorter than this are not used as separating axes.
+const AXIS_EPSILON: f32 = 1e-12;
+
+/// Colored voxels on an integer grid. Voxel `[x, y, z]` fills the unit cube
+/// from that corner, scaled by the voxel size when meshed.
+pub trait VoxelVolume {
+  /// Smallest coordinate of any voxel and one past the largest, or an
+  /// empty range when there are none.
+  fn bounds(&self) -> ([i32; 3], [i32; 3]);
+
+  fn get(&self, coord: [i32; 3]) -> Option<[f32;4]>;
+}
+
+/// Voxels stored for every cell of a box, for small or mostly solid volumes.
+#[derive(Clone, Debug)]
+pub struct DenseVoxels {
+  min: [i32; 3],
+  size: [usize; 3],
+  voxels: Vec<Option<[f32;4]>>,
+}
+
+impl DenseVoxels {
+  /// Empty box of `size` cells with its first cell at `min`.
+  pub fn new(min: [i32; 3], size: [usize; 3]) -> Self {
+    Self {
+      min,
+      size,
+      voxels: vec![None; size[0] * size[1] * size[2]],
+    }
+  }
+
+  /// Fills every empty cell that cannot be reached from the sides of the
+  /// box without passing through a voxel, so closed shells become solid.
+  /// Filled cells take the color of the voxel before them along X.
+  pub fn fill_interior(&mut self) {
+    if self.voxels.is_empty() {
+      return;
+    }
+
+    let mut outside = vec![false; self.voxels.len()];
+    let mut stack = Vec::new();
+
+    for (index, voxel) in self.voxels.iter().enumerate() {
+      let cell = self.cell(index);
+      let on_side = (0..3).any(|axis| cell[axis] == 0 || cell[axis] + 1 == self.size[axis]);
+
+      if on_side && voxel.is_none() {
+        outside[index] = true;
+        stack.push(index);
+      }
+    }
+
+    while let Some(index) = stack.pop() {
+      let cell = self.cell(index);
+
+      for axis in 0..3 {
+        for step in [-1, 1] {
+          let mut neighbour = cell.map(|value| value as i32);
+
+          neighbour[axis] += step;
+
+          if let Some(next) = self.index(self.coord(neighbour)) {
+            if !outside[next] && self.voxels[next].is_none() {
+              outside[next] = true;
+              stack.push(next);
+            }
+          }
+        }
+      }
+    }
+
+    for (row, outside) in self.voxels.chunks_mut(self.size[0]).zip(outside.chunks(self.size[0])) {
+      let mut color = None;
+
+      for (voxel, &outside) in row.iter_mut().zip(outside) {
+        match voxel {
+          Some(voxel) => color = Some(*voxel),
+          None if !outside => *voxel = color,
+          None => {}
+        }
+      }
+    }
+  }
+
+  /// Sets or clears one voxel. Coordinates outside the box are ignored.
+  pub fn set(&mut self, coord: [i32; 3], color: Option<[f32;4]>) {
+    if let Some(index) = self.index(coord) {
+      self.voxels[index] = color;
+    }
+  }
+
+  /// Cell of an index, counted from the first cell of the box.
+  fn cell(&self, index: usize) -> [usize; 3] {
+    [
+      index % self.size[0],
+      index / self.size[0] % self.size[1],
+      index / (self.size[0] * self.size[1]),
+    ]
+  }
+
+  fn coord(&self, cell: [i32; 3]) -> [i32; 3] {
+    [cell[0] + self.min[0], cell[1] + self.min[1], cell[2] + self.min[2]]
+  }
+
+  fn index(&self, coord: [i32; 3]) -> Option<usize> {
+    let mut index = 0;
+
+    for axis in (0..3).rev() {
+      let offset = coord[axis] - self.min[axis];
+
+      if offset < 0 || offset as usize >= self.size[axis] {
+        return None;
+      }
+
+      index = index * self.size[axis] + offset as usize;
+    }
+
+    Some(index)
+  }
+}
+
+impl From<&SparseVoxels> for DenseVoxels {
+  fn from(sparse: &SparseVoxels) -> Self {
+    let (min, max) = sparse.bounds();
+    let size = [0, 1, 2].map(|axis| (max[axis] - min[axis]).max(0) as usize);
+    let mut dense = Self::new(min, size);
+
+    for (&coord, &color) in &sparse.voxels {
+      dense.set(coord, Some(color));
+    }
+
+    dense
+  }
+}
+
+impl VoxelVolume for DenseVoxels {
+  fn bounds(&self) -> ([i32; 3], [i32; 3]) {
+    (self.min, self.coord(self.size.map(|size| size as i32)))
+  }
+
+  fn get(&self, coord: [i32; 3]) -> Option<[f32;4]> {
+    self.index(coord).and_then(|index| self.voxels[index])
+  }
+}
+
+/// Voxels stored by coordinate, for large or mostly empty volumes without
+/// fixed bounds.
+#[derive(Clone, Debug, Default)]
+pub struct SparseVoxels {
+  voxels: HashMap<[i32; 3], [f32;4]>,
+}
+
+impl SparseVoxels {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set(&mut self, coord: [i32; 3], color: Option<[f32;4]>) {
+    match color {
+      Some(color) => self.voxels.insert(coord, color),
+      None => self.voxels.remove(&coord),
+    };
+  }
+}
+
+impl VoxelVolume for SparseVoxels {
+  fn bounds(&self) -> ([i32; 3], [i32; 3]) {
+    if self.voxels.is_empty() {
+      return ([0; 3], [0; 3]);
+    }
+
+    self.voxels.keys().fold(([i32::MAX; 3], [i32::MIN; 3]), |(min, max), coord| {
+      ([0, 1, 2].map(|axis| min[axis].min(coord[axis])), [0, 1, 2].map(|axis| max[axis].max(coord[axis] + 1)))
+    })
+  }
+
+  fn get(&self, coord: [i32; 3]) -> Option<[f32;4]> {
+    self.voxels.get(&coord).copied()
+  }
+}
+
+/// Visible faces of the voxels, `voxel_size` wide. Neighbouring faces in
+/// the same plane with the same color are merged greedily into the largest
+/// rectangles that fit, and each rectangle becomes a single quad.
+///
+/// Merged quads do not split where a smaller face meets their side, so the
+/// mesh has T-junctions there: it is closed in space but not welded, and
+/// those edges count as boundary edges. An L of three voxels has twelve,
+/// six at each of its flat sides.
+pub fn build(volume: &impl VoxelVolume, voxel_size: f32) -> MeshBuilder {
+  let mut builder = MeshBuilder::new("Voxels");
+  let (min, max) = volume.bounds();
+
+  for axis in 0..3 {
+    // `u` cross `v` points along `axis`.
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let width = (max[u] - min[u]).max(0) as usize;
+    let height = (max[v] - min[v]).max(0) as usize;
+    let mut mask = vec![None; width * height];
+
+    for side in [-1, 1] {
+      for layer in min[axis]..max[axis] {
+        for j in 0..height {
+          for i in 0..width {
+            let mut coord = [0; 3];
+
+            coord[axis] = layer;
+            coord[u] = min[u] + i as i32;
+            coord[v] = min[v] + j as i32;
+
+            let mut neighbour = coord;
+
+            neighbour[axis] += side;
+            mask[j * width + i] = volume.get(coord).filter(|_| volume.get(neighbour).is_none());
+          }
+        }
+
+        let plane = if side > 0 { layer + 1 } else { layer };
+
+        for (i, j, w, h, color) in merge_faces(&mut mask, width, height) {
+          let mut corner = Vector3::new(0.0, 0.0, 0.0);
+          let (mut along_u, mut along_v) = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+
+          corner[axis] = plane as f32;
+          corner[u] = (min[u] + i as i32) as f32;
+          corner[v] = (min[v] + j as i32) as f32;
+          along_u[u] = w as f32;
+          along_v[v] = h as f32;
+          let (width, length) = if side > 0 { (along_v, along_u) } else { (along_u, along_v) };
+
+          builder.add_colored_quad(corner * voxel_size, width * voxel_size, length * voxel_size, color);
+        }
+      }
+    }
+  }
+
+  builder
+}
+
+/// Voxels touched by the triangles of a model, in its local space and
+/// colored with the average color of those triangles, as their vertex
+/// colors tinted by their material. The inside of closed meshes is filled,
+/// so only the outside gets faces when meshed. `voxel_size` must be
+/// positive.
+pub fn voxelize(model: &Model, voxel_size: f32) -> DenseVoxels {
+  let mut sums: HashMap<[i32; 3], ([f32;4], f32)> = HashMap::new();
+  let half = voxel_size / 2.0;
+
+  for mesh in &model.meshes {
+    let tint = model.materials.get(mesh.material).map_or(DEFAULT_TINT, Material::tint);
+
+    for face in mesh.indices.chunks_exact(3) {
+      let vertices = [0, 1, 2].map(|corner| mesh.vertices[face[corner] as usize]);
+      let triangle = vertices.map(|vertex| Vector3::from(vertex.position));
+      let first = [0, 1, 2].map(|axis| (triangle.iter().map(|point| point[axis]).fold(f32::MAX, f32::min) / voxel_size).floor() as i32);
+      let last = [0, 1, 2].map(|axis| (triangle.iter().map(|point| point[axis]).fold(f32::MIN, f32::max) / voxel_size).floor() as i32);
+      let color = [0, 1, 2, 3].map(|channel| vertices.iter().map(|vertex| vertex.color[channel]).sum::<f32>() / 3.0 * tint[channel]);
+
+      for x in first[0]..=last[0] {
+        for y in first[1]..=last[1] {
+          for z in first[2]..=last[2] {
+            let center = Vector3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5) * voxel_size;
+
+            if triangle_overlaps_box(triangle, center, half) {
+              let sum = sums.entry([x, y, z]).or_insert(([0.0; 4], 0.0));
+
+              sum.0 = [0, 1, 2, 3].map(|channel| sum.0[channel] + color[channel]);
+              sum.1 += 1.0;
+            }
+          }
+        }
+      }
+    }
+  }
+
+  let mut surface = SparseVoxels::new();
+
+  for (coord, (sum, count)) in sums {
+    surface.set(coord, Some(sum.map(|channel| channel / count)));
+  }
+
+  let mut voxels = DenseVoxels::from(&surface);
+
+  voxels.fill_interior();
+  voxels
+}
+
+/// Takes the largest rectangles of equal faces out of a `width` by
+/// `height` mask, as their first cell, size and color.
+fn merge_faces(mask: &mut [Option<[f32;4]>], width: usize, height: usize) -> Vec<(usize, usize, usize, usize, [f32;4])> {
+  let mut rectangles = Vec::new();
+
+  for j in 0..height {
+    let mut i = 0;
+
+    while i < width {
+      let color = match mask[j * width + i] {
+        Some(color) => color,
+        None => {
+          i += 1;
+          continue;
+        }
+      };
+      let w = (i..width).take_while(|&k| mask[j * width + k] == Some(color)).count();
+      let h = (j..height)
+        .take_while(|&row| (i..i + w).all(|k| mask[row * width + k] == Some(color)))
+        .count();
+
+      for row in j..j + h {
+        for cell in &mut mask[row * width + i..row * width + i + w] {
+          *cell = None;
+        }
+      }
+
+      rectangles.push((i, j, w, h, color));
+      i += w;
+    }
+  }
+
+  rectangles
+}
+
+/// Separating axis test between a triangle and the cube around `center`
+/// reaching `half` along each axis.
+fn triangle_overlaps_box(triangle: [Vector3<f32>; 3], center: Vector3<f32>, half: f32) -> bool {
+  let points = triangle.map(|point| point - center);
+  let edges = [points[1] - points[0], points[2] - points[1], points[0] - points[2]];
+  let units = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+  let mut axes = vec![edges[0].cross(edges[1])];
+
+  axes.extend(units);
+
+  for edge in edges {
+    axes.extend(units.iter().map(|unit| edge.cross(*unit)));
+  }
+
+  axes.into_iter().filter(|axis| axis.magnitude2() > AXIS_EPSILON).all(|axis| {
+    let reach = half * (axis.x.abs() + axis.y.abs() + axis.z.abs());
+    let projections = points.map(|point| point.dot(axis));
+    let low = projections.iter().copied().fold(f32::MAX, f32::min);
+    let high = projections.iter().copied().fold(f32::MIN, f32::max);
+
+    low <= reach && high >= -reach
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const RED: [f32;4] = [1.0, 0.0, 0.0, 1.0];
+  const BLUE: [f32;4] = [0.0, 0.0, 1.0, 1.0];
+
+  /// Box of three voxels a side with only its outer layer set, and the
+  /// voxel before the center along X blue.
+  fn shell() -> DenseVoxels {
+    let mut voxels = DenseVoxels::new([0; 3], [3; 3]);
+
+    for x in 0..3 {
+      for y in 0..3 {
+        for z in 0..3 {
+          if [x, y, z] != [1; 3] {
+            voxels.set([x, y, z], Some(RED));
+          }
+        }
+      }
+    }
+
+    voxels.set([0, 1, 1], Some(BLUE));
+    voxels
+  }
+
+  #[test]
+  fn merges_equal_faces_into_rectangles() {
+    let mut mask = vec![Some(RED); 6];
+
+    assert_eq!(merge_faces(&mut mask, 3, 2), vec![(0, 0, 3, 2, RED)]);
+    assert!(mask.iter().all(Option::is_none));
+
+    let mut mask = vec![Some(RED), Some(RED), Some(BLUE), Some(RED), Some(RED), None];
+
+    assert_eq!(merge_faces(&mut mask, 3, 2), vec![(0, 0, 2, 2, RED), (2, 0, 1, 1, BLUE)]);
+  }
+
+  #[test]
+  fn fills_closed_shells() {
+    let mut voxels = shell();
+
+    voxels.fill_interior();
+
+    assert_eq!(voxels.get([1; 3]), Some(BLUE));
+  }
+
+  #[test]
+  fn leaves_open_shells_hollow() {
+    let mut voxels = shell();
+
+    voxels.set([1, 1, 0], None);
+    voxels.fill_interior();
+
+    assert_eq!(voxels.get([1; 3]), None);
+  }
+
+  #[test]
+  fn builds_a_closed_box_for_one_voxel() {
+    let mut voxels = SparseVoxels::new();
+
+    voxels.set([0; 3], Some(RED));
+
+    let builder = build(&voxels, 2.0);
+    let report = builder.validate();
+    let meshes = builder.build();
+    let center = Vector3::new(1.0, 1.0, 1.0);
+
+    assert!(report.is_valid(), "{}", report);
+    assert_eq!(report.boundary_edges, 0);
+    assert_eq!(meshes.iter().map(|mesh| mesh.indices.len() / 3).sum::<usize>(), 12);
+
+    for mesh in &meshes {
+      for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(mesh.vertices[triangle[i] as usize].position));
+
+        assert!((b - a).cross(c - a).dot((a + b + c) / 3.0 - center) > 0.0);
+      }
+    }
+  }
+
+  #[test]
+  fn leaves_t_junctions_where_merged_faces_meet() {
+    let mut voxels = SparseVoxels::new();
+
+    for coord in [[0, 0, 0], [1, 0, 0], [0, 1, 0]] {
+      voxels.set(coord, Some(RED));
+    }
+
+    let report = build(&voxels, 1.0).validate();
+
+    assert!(report.is_valid(), "{}", report);
+    assert_eq!(report.boundary_edges, 12);
+  }
+}